@@ -0,0 +1,324 @@
+// bvh.rs
+
+use raylib::prelude::Vector3;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::ray_intersect::{Intersect, RayIntersect};
+
+const MAX_LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3, max: Vector3) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn empty() -> Self {
+        Aabb {
+            min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn grow(&self, point: Vector3) -> Aabb {
+        Aabb {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn centroid(&self) -> Vector3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test: distance at which the ray enters the box, if it does before `t_max`
+    pub fn hit(&self, ray_origin: &Vector3, inverse_direction: &Vector3, t_max: f32) -> Option<f32> {
+        let t0 = (self.min - *ray_origin) * *inverse_direction;
+        let t1 = (self.max - *ray_origin) * *inverse_direction;
+
+        let t_enter = t0.x.min(t1.x).max(t0.y.min(t1.y)).max(t0.z.min(t1.z));
+        let t_exit = t0.x.max(t1.x).min(t0.y.max(t1.y)).min(t0.z.max(t1.z));
+
+        if t_enter <= t_exit && t_exit > 0.0 && t_enter < t_max {
+            Some(t_enter.max(0.0))
+        } else {
+            None
+        }
+    }
+}
+
+fn axis_value(v: Vector3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+enum BvhNode {
+    Leaf { bounds: Aabb, first: usize, count: usize },
+    Interior { bounds: Aabb, left: usize, right: usize },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } => bounds,
+            BvhNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over the scene objects.
+///
+/// The tree stores indices into the object list it was built from, so the same
+/// slice must be passed to the queries.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+    build_time: Duration,
+}
+
+impl Bvh {
+    pub fn build(objects: &[Arc<dyn RayIntersect>]) -> Bvh {
+        let start = Instant::now();
+
+        let boxes: Vec<Aabb> = objects.iter().map(|object| object.bounding_box()).collect();
+        let mut indices: Vec<usize> = (0..objects.len()).collect();
+        let mut nodes = Vec::new();
+
+        if !objects.is_empty() {
+            build_node(&mut nodes, &mut indices, 0, &boxes);
+        }
+
+        Bvh {
+            nodes,
+            indices,
+            build_time: start.elapsed(),
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn build_time(&self) -> Duration {
+        self.build_time
+    }
+
+    pub fn closest_hit(
+        &self,
+        objects: &[Arc<dyn RayIntersect>],
+        ray_origin: &Vector3,
+        ray_direction: &Vector3,
+    ) -> Intersect {
+        let mut closest = Intersect::empty();
+        let mut zbuffer = f32::INFINITY;
+
+        self.traverse(ray_origin, ray_direction, f32::INFINITY, |first, count, zbuffer_limit| {
+            for &index in &self.indices[first..first + count] {
+                let i = objects[index].ray_intersect(ray_origin, ray_direction);
+                if i.is_intersecting && i.distance < zbuffer {
+                    zbuffer = i.distance;
+                    closest = i;
+                }
+            }
+            *zbuffer_limit = zbuffer;
+            false
+        });
+
+        closest
+    }
+
+    pub fn any_hit(
+        &self,
+        objects: &[Arc<dyn RayIntersect>],
+        ray_origin: &Vector3,
+        ray_direction: &Vector3,
+        max_distance: f32,
+    ) -> bool {
+        let mut blocked = false;
+
+        self.traverse(ray_origin, ray_direction, max_distance, |first, count, _| {
+            blocked = self.indices[first..first + count].iter().any(|&index| {
+                let i = objects[index].ray_intersect(ray_origin, ray_direction);
+                i.is_intersecting && i.distance < max_distance
+            });
+            blocked
+        });
+
+        blocked
+    }
+
+    /// Walks the nodes hit by the ray before `t_max`, calling `visit_leaf(first, count, t_max)` for every leaf.
+    /// The visitor may lower `t_max` to prune farther nodes and returns `true` to stop early.
+    fn traverse<F>(&self, ray_origin: &Vector3, ray_direction: &Vector3, mut t_max: f32, mut visit_leaf: F)
+    where
+        F: FnMut(usize, usize, &mut f32) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inverse_direction = Vector3::new(
+            1.0 / ray_direction.x,
+            1.0 / ray_direction.y,
+            1.0 / ray_direction.z,
+        );
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.bounds().hit(ray_origin, &inverse_direction, t_max).is_none() {
+                continue;
+            }
+
+            match node {
+                BvhNode::Leaf { first, count, .. } => {
+                    if visit_leaf(*first, *count, &mut t_max) {
+                        return;
+                    }
+                }
+                BvhNode::Interior { left, right, .. } => {
+                    // Visitar primero el hijo más cercano para podar antes el más lejano
+                    let left_t = self.nodes[*left].bounds().hit(ray_origin, &inverse_direction, t_max);
+                    let right_t = self.nodes[*right].bounds().hit(ray_origin, &inverse_direction, t_max);
+                    match (left_t, right_t) {
+                        (Some(l), Some(r)) if l <= r => {
+                            stack.push(*right);
+                            stack.push(*left);
+                        }
+                        (Some(_), Some(_)) => {
+                            stack.push(*left);
+                            stack.push(*right);
+                        }
+                        (Some(_), None) => stack.push(*left),
+                        (None, Some(_)) => stack.push(*right),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Builds the subtree for `indices` (which starts at `offset` in the full index list)
+/// by splitting at the median centroid along the longest axis. Returns the node index.
+fn build_node(nodes: &mut Vec<BvhNode>, indices: &mut [usize], offset: usize, boxes: &[Aabb]) -> usize {
+    let bounds = indices
+        .iter()
+        .fold(Aabb::empty(), |acc, &index| acc.union(&boxes[index]));
+
+    if indices.len() <= MAX_LEAF_SIZE {
+        nodes.push(BvhNode::Leaf {
+            bounds,
+            first: offset,
+            count: indices.len(),
+        });
+        return nodes.len() - 1;
+    }
+
+    let centroid_bounds = indices
+        .iter()
+        .fold(Aabb::empty(), |acc, &index| acc.grow(boxes[index].centroid()));
+    let axis = centroid_bounds.longest_axis();
+
+    indices.sort_by(|&a, &b| {
+        let ca = axis_value(boxes[a].centroid(), axis);
+        let cb = axis_value(boxes[b].centroid(), axis);
+        ca.total_cmp(&cb)
+    });
+
+    let node_index = nodes.len();
+    nodes.push(BvhNode::Leaf { bounds, first: offset, count: 0 });
+
+    let middle = indices.len() / 2;
+    let (left_indices, right_indices) = indices.split_at_mut(middle);
+    let left = build_node(nodes, left_indices, offset, boxes);
+    let right = build_node(nodes, right_indices, offset + middle, boxes);
+
+    nodes[node_index] = BvhNode::Interior { bounds, left, right };
+    node_index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::Cube;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_point(rng: &mut StdRng, extent: f32) -> Vector3 {
+        Vector3::new(
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+        )
+    }
+
+    fn random_scene(rng: &mut StdRng) -> Vec<Arc<dyn RayIntersect>> {
+        (0..80)
+            .map(|index| -> Arc<dyn RayIntersect> {
+                let center = random_point(rng, 10.0);
+                if index % 2 == 0 {
+                    Arc::new(Sphere { center, radius: rng.gen_range(0.2..1.5), material: Material::black() })
+                } else {
+                    Arc::new(Cube { center, size: rng.gen_range(0.2..2.0), material: Material::black() })
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let objects = random_scene(&mut rng);
+        let bvh = Bvh::build(&objects);
+
+        for _ in 0..2000 {
+            let origin = random_point(&mut rng, 14.0);
+            let direction = random_point(&mut rng, 1.0).normalized();
+            let max_distance = rng.gen_range(0.5..30.0);
+
+            let nearest = objects
+                .iter()
+                .map(|object| object.ray_intersect(&origin, &direction))
+                .filter(|hit| hit.is_intersecting)
+                .map(|hit| hit.distance)
+                .fold(f32::INFINITY, f32::min);
+
+            let hit = bvh.closest_hit(&objects, &origin, &direction);
+            assert_eq!(hit.is_intersecting, nearest.is_finite());
+            if hit.is_intersecting {
+                assert_eq!(hit.distance, nearest);
+            }
+            assert_eq!(bvh.any_hit(&objects, &origin, &direction, max_distance), nearest < max_distance);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// clouds.rs
+
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use raylib::prelude::*;
+
+use crate::fog::henyey_greenstein;
+use crate::light::{light_to_irradiance, smoothstep, LightKind};
+use crate::scene::Scene;
+
+// Pasos hacia la luz para estimar cuánta nube hay entre cada muestra y el sol
+const LIGHT_STEPS: u32 = 4;
+
+// Las nubes dispersan sobre todo hacia adelante, lo que ilumina sus bordes a contraluz
+const CLOUD_ANISOTROPY: f32 = 0.4;
+
+/// Horizontal slab of noise-based clouds between two heights.
+///
+/// Camera rays are ray marched through the slab. At each sample the directional lights
+/// (the sun and the moon) are attenuated by the cloud between the sample and the top or
+/// bottom of the layer, which shades the clouds' own far side, and by the scene geometry,
+/// so the island casts its shadow on the cloud sea below it.
+pub struct CloudLayer {
+    pub bottom: f32,
+    pub top: f32,
+    /// Fraction of the sky covered, 0 clear to 1 overcast
+    pub coverage: f32,
+    /// Extinction coefficient of the thickest parts of the clouds, per unit of distance
+    pub density: f32,
+    pub scale: f32,
+    pub steps: u32,
+    pub distance: f32,
+    noise: Fbm<Perlin>,
+}
+
+impl CloudLayer {
+    pub fn new(bottom: f32, top: f32, seed: u32) -> Self {
+        CloudLayer {
+            bottom,
+            top,
+            coverage: 0.5,
+            density: 1.0,
+            scale: 4.0,
+            steps: 24,
+            distance: 150.0,
+            noise: Fbm::<Perlin>::new(seed).set_octaves(4),
+        }
+    }
+
+    pub fn density_at(&self, point: Vector3) -> f32 {
+        let height = (point.y - self.bottom) / (self.top - self.bottom).max(1e-4);
+        if !(0.0..=1.0).contains(&height) || self.coverage <= 0.0 {
+            return 0.0;
+        }
+
+        let scale = self.scale.max(1e-3) as f64;
+        let noise = self.noise.get([
+            point.x as f64 / scale,
+            point.y as f64 / scale,
+            point.z as f64 / scale,
+        ]) as f32;
+
+        // Solo la parte más alta del ruido se vuelve nube; más cobertura baja el umbral
+        let coverage = self.coverage.min(1.0);
+        let cloud = ((noise * 0.5 + 0.5 - (1.0 - coverage)) / coverage).max(0.0);
+        // Base plana y cima redondeada
+        let profile = smoothstep(0.0, 0.1, height) * (1.0 - smoothstep(0.4, 1.0, height));
+        cloud * profile * self.density
+    }
+
+    /// Stretch of the ray, up to `far` from its origin, that lies inside the layer, if any
+    fn span(&self, origin: Vector3, direction: Vector3, far: f32) -> Option<(f32, f32)> {
+        let (enter, exit) = if direction.y.abs() < 1e-6 {
+            if origin.y < self.bottom || origin.y > self.top {
+                return None;
+            }
+            (0.0, f32::INFINITY)
+        } else {
+            let to_bottom = (self.bottom - origin.y) / direction.y;
+            let to_top = (self.top - origin.y) / direction.y;
+            (to_bottom.min(to_top), to_bottom.max(to_top))
+        };
+
+        let enter = enter.max(0.0);
+        let exit = exit.min(far).min(self.distance);
+        (enter < exit).then_some((enter, exit))
+    }
+
+    fn light_transmittance(&self, point: Vector3, light_dir: Vector3) -> f32 {
+        let thickness = self.top - self.bottom;
+        let Some((_, exit)) = self.span(point, light_dir, thickness * 3.0) else {
+            return 1.0;
+        };
+
+        let step = exit / LIGHT_STEPS as f32;
+        let optical_depth: f32 = (0..LIGHT_STEPS)
+            .map(|index| self.density_at(point + light_dir * ((index as f32 + 0.5) * step)) * step)
+            .sum();
+        (-optical_depth).exp()
+    }
+
+    pub fn march(&self, origin: Vector3, direction: Vector3, distance: f32, scene: &Scene) -> (Vector3, f32) {
+        let Some((enter, exit)) = self.span(origin, direction, distance) else {
+            return (Vector3::zero(), 1.0);
+        };
+
+        let steps = self.steps.max(1);
+        let step = (exit - enter) / steps as f32;
+        let ambient = sky_ambient(scene);
+        let jitter = rand::random::<f32>();
+
+        let mut inscatter = Vector3::zero();
+        let mut transmittance = 1.0;
+        for index in 0..steps {
+            let point = origin + direction * (enter + (index as f32 + jitter) * step);
+            let density = self.density_at(point);
+            if density <= 0.0 {
+                continue;
+            }
+
+            let mut incoming = ambient;
+            for light in &scene.lights {
+                let LightKind::Directional { direction: light_travel } = light.kind else {
+                    continue;
+                };
+                if light.intensity <= 0.0 {
+                    continue;
+                }
+                let light_dir = -light_travel;
+                if scene.occluded(&point, &light_dir, f32::INFINITY) {
+                    continue;
+                }
+                let irradiance = light_to_irradiance(light.intensity * self.light_transmittance(point, light_dir));
+                let phase = henyey_greenstein(direction.dot(light_dir), CLOUD_ANISOTROPY);
+                incoming += light.color_vector() * (irradiance * phase);
+            }
+
+            inscatter += incoming * (density * step * transmittance);
+            transmittance *= (-density * step).exp();
+            if transmittance < 1e-3 {
+                break;
+            }
+        }
+
+        (inscatter, transmittance)
+    }
+}
+
+/// Light the clouds get from the rest of the sky: what the sky shows straight up, or the
+/// scene's ambient light when there is no physical sky
+fn sky_ambient(scene: &Scene) -> Vector3 {
+    match (&scene.sky, &scene.ambient) {
+        (Some(sky), _) => sky.radiance(Vector3::new(0.0, 1.0, 0.0)),
+        (None, Some(ambient)) => ambient.radiance(),
+        (None, None) => Vector3::zero(),
+    }
+}
@@ -1,6 +1,7 @@
 use raylib::prelude::Vector3;
 use crate::ray_intersect::{Intersect, RayIntersect};
 use crate::material::Material;
+use crate::bvh::Aabb;
 
 pub struct Cube {
     pub center: Vector3,
@@ -80,4 +81,45 @@ impl RayIntersect for Cube {
         
         Intersect::empty()
     }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let half = Vector3::new(self.size * 0.5, self.size * 0.5, self.size * 0.5);
+        Aabb::new(self.center - half, self.center + half)
+    }
+
+    fn sample_surface(&self, from: Vector3) -> (Vector3, Vector3, f32) {
+        let half_size = self.size * 0.5;
+        let axes = [
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        ];
+
+        // Caras cuya normal apunta hacia `from`
+        let mut faces = Vec::with_capacity(3);
+        for (axis, normal) in axes.iter().enumerate() {
+            for sign in [-1.0, 1.0] {
+                let face_normal = *normal * sign;
+                let face_center = self.center + face_normal * half_size;
+                if (from - face_center).dot(face_normal) > 0.0 {
+                    faces.push((axis, face_normal));
+                }
+            }
+        }
+        if faces.is_empty() {
+            faces.push((1, axes[1]));
+        }
+
+        let (axis, face_normal) = faces[rand::random::<usize>() % faces.len()];
+        let tangent = axes[(axis + 1) % 3];
+        let bitangent = axes[(axis + 2) % 3];
+        let s = (rand::random::<f32>() - 0.5) * self.size;
+        let t = (rand::random::<f32>() - 0.5) * self.size;
+        let point = self.center + face_normal * half_size + tangent * s + bitangent * t;
+        (point, face_normal, faces.len() as f32 * self.size * self.size)
+    }
 }
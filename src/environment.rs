@@ -0,0 +1,132 @@
+// environment.rs
+
+use raylib::prelude::Vector3;
+use std::f32::consts::PI;
+
+use crate::sky::Sky;
+use crate::skybox_color;
+use crate::textures::TextureManager;
+use crate::tonemap::luminance;
+
+// Resolución de la tabla con la que se muestrea el cielo
+const MAP_WIDTH: usize = 128;
+const MAP_HEIGHT: usize = 64;
+
+/// Lights the scene with the sky itself: the physical sky, the skybox image or the procedural sky.
+///
+/// The sky is tabulated into an equirectangular grid, and shadow rays pick cells with
+/// probability proportional to their brightness, so a small sun in a sunny skybox gets
+/// most of the samples instead of being found by chance.
+pub struct EnvironmentLight {
+    pub intensity: f32,
+    pub samples: u32,
+    map: Option<EnvironmentMap>,
+}
+
+struct EnvironmentMap {
+    radiance: Vec<Vector3>,
+    // Distribución acumulada de las filas y, dentro de cada fila, de sus columnas
+    row_cdf: Vec<f32>,
+    column_cdfs: Vec<Vec<f32>>,
+    cell_probability: Vec<f32>,
+}
+
+impl EnvironmentLight {
+    pub fn new(intensity: f32, samples: u32) -> Self {
+        EnvironmentLight {
+            intensity,
+            samples: samples.max(1),
+            map: None,
+        }
+    }
+
+    /// Tabulates the sky; has to run once the skybox texture is loaded, and again whenever the sky changes
+    pub fn build(&mut self, skybox: Option<&str>, sky: Option<&Sky>, texture_manager: &TextureManager) {
+        let mut radiance = Vec::with_capacity(MAP_WIDTH * MAP_HEIGHT);
+        let mut weights = Vec::with_capacity(MAP_WIDTH * MAP_HEIGHT);
+        for row in 0..MAP_HEIGHT {
+            for column in 0..MAP_WIDTH {
+                let direction = cell_direction(column as f32 + 0.5, row as f32 + 0.5);
+                // El disco del sol ya ilumina como luz direccional, así que solo se tabula la bóveda
+                let color = match sky {
+                    Some(sky) => sky.radiance(direction),
+                    None => skybox_color(direction, skybox, None, texture_manager),
+                };
+                // Las filas cerca de los polos cubren menos ángulo sólido
+                let solid_angle = (1.0 - direction.y * direction.y).max(0.0).sqrt();
+                radiance.push(color);
+                weights.push(luminance(color).max(0.0) * solid_angle);
+            }
+        }
+
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            self.map = None;
+            return;
+        }
+
+        let mut row_cdf = Vec::with_capacity(MAP_HEIGHT);
+        let mut column_cdfs = Vec::with_capacity(MAP_HEIGHT);
+        let mut accumulated = 0.0;
+        for row in weights.chunks(MAP_WIDTH) {
+            let row_total: f32 = row.iter().sum();
+            let mut column_cdf = Vec::with_capacity(MAP_WIDTH);
+            let mut row_accumulated = 0.0;
+            for weight in row {
+                row_accumulated += weight;
+                column_cdf.push(if row_total > 0.0 { row_accumulated / row_total } else { 1.0 });
+            }
+            accumulated += row_total;
+            row_cdf.push(accumulated / total);
+            column_cdfs.push(column_cdf);
+        }
+
+        self.map = Some(EnvironmentMap {
+            radiance,
+            row_cdf,
+            column_cdfs,
+            cell_probability: weights.iter().map(|weight| weight / total).collect(),
+        });
+    }
+
+    /// Random direction towards the sky and the radiance arriving from it divided by the
+    /// probability density (per solid angle) of having picked it
+    pub fn sample(&self) -> Option<(Vector3, Vector3)> {
+        let map = self.map.as_ref()?;
+
+        let row = search(&map.row_cdf, rand::random::<f32>());
+        let column = search(&map.column_cdfs[row], rand::random::<f32>());
+        let cell = row * MAP_WIDTH + column;
+
+        let direction = cell_direction(
+            column as f32 + rand::random::<f32>(),
+            row as f32 + rand::random::<f32>(),
+        );
+
+        let cos_elevation = (1.0 - direction.y * direction.y).max(0.0).sqrt();
+        let cell_solid_angle = (2.0 * PI / MAP_WIDTH as f32) * (PI / MAP_HEIGHT as f32) * cos_elevation;
+        if cell_solid_angle <= 0.0 {
+            return None;
+        }
+        let pdf = map.cell_probability[cell] / cell_solid_angle;
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((direction, map.radiance[cell] * (self.intensity / pdf)))
+    }
+}
+
+/// Direction through the point (`x`, `y`) of the grid, the inverse of the skybox's equirect mapping
+fn cell_direction(x: f32, y: f32) -> Vector3 {
+    let u = x / MAP_WIDTH as f32;
+    let v = y / MAP_HEIGHT as f32;
+    let phi = (u - 0.5) * 2.0 * PI;
+    let elevation = (0.5 - v) * PI;
+    let horizontal = elevation.cos();
+    Vector3::new(horizontal * phi.sin(), elevation.sin(), horizontal * phi.cos())
+}
+
+fn search(cdf: &[f32], value: f32) -> usize {
+    cdf.partition_point(|&c| c < value).min(cdf.len() - 1)
+}
@@ -0,0 +1,127 @@
+// fog.rs
+
+use raylib::prelude::*;
+use std::f32::consts::PI;
+
+use crate::light::{light_to_irradiance, LightSample};
+use crate::scene::Scene;
+use crate::shadow_transmittance;
+use crate::tonemap::decode_srgb;
+
+/// Henyey-Greenstein phase function: share of the light scattered by `acos(cos_theta)` away
+/// from its direction of travel, for asymmetry `g` (0 even, towards 1 mostly forwards)
+pub fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let g = g.clamp(-0.95, 0.95);
+    let denominator = (1.0 + g * g - 2.0 * g * cos_theta).max(1e-4);
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FogKind {
+    Homogeneous,
+    /// Full density below `height`, thinning out exponentially above it at rate `falloff`
+    Height { height: f32, falloff: f32 },
+}
+
+pub struct Fog {
+    pub kind: FogKind,
+    /// Extinction coefficient: fraction of light lost per unit of distance at full density
+    pub density: f32,
+    /// Color of the light the fog scatters (its albedo)
+    pub color: Color,
+    pub anisotropy: f32,
+    pub steps: u32,
+    /// Fog only fills this far from the ray origin; rays that escape the scene cross all of it
+    pub distance: f32,
+}
+
+impl Fog {
+    pub fn density_at(&self, point: Vector3) -> f32 {
+        match self.kind {
+            FogKind::Homogeneous => self.density,
+            FogKind::Height { height, falloff } => self.density * (-(point.y - height) * falloff).exp().min(1.0),
+        }
+    }
+
+    fn albedo(&self) -> Vector3 {
+        decode_srgb(Vector3::new(
+            self.color.r as f32 / 255.0,
+            self.color.g as f32 / 255.0,
+            self.color.b as f32 / 255.0,
+        ))
+    }
+
+    fn phase(&self, direction: Vector3, light_dir: Vector3) -> f32 {
+        henyey_greenstein(direction.dot(light_dir), self.anisotropy)
+    }
+
+    /// Light scattered towards the ray origin by the fog along the first `distance` units of
+    /// the ray, and the fraction of the light from further away that makes it through
+    pub fn march(&self, origin: Vector3, direction: Vector3, distance: f32, scene: &Scene) -> (Vector3, f32) {
+        let length = distance.min(self.distance);
+        let steps = self.steps.max(1);
+        if length <= 0.0 || self.density <= 0.0 {
+            return (Vector3::zero(), 1.0);
+        }
+
+        let step = length / steps as f32;
+        let albedo = self.albedo();
+        let ambient = scene.ambient.as_ref().map_or(Vector3::zero(), |ambient| ambient.radiance());
+        // Un desfase aleatorio por rayo cambia las bandas de los pasos fijos por ruido
+        let jitter = rand::random::<f32>();
+
+        let mut inscatter = Vector3::zero();
+        let mut transmittance = 1.0;
+        for index in 0..steps {
+            let point = origin + direction * ((index as f32 + jitter) * step);
+            let density = self.density_at(point);
+            if density <= 0.0 {
+                continue;
+            }
+
+            let incoming = ambient + self.incoming_light(point, direction, scene);
+            inscatter += albedo * incoming * (density * step * transmittance);
+            transmittance *= (-density * step).exp();
+            if transmittance < 1e-3 {
+                break;
+            }
+        }
+
+        (inscatter, transmittance)
+    }
+
+    /// Light reaching `point` from one random sample of each light, weighted by the phase function
+    fn incoming_light(&self, point: Vector3, direction: Vector3, scene: &Scene) -> Vector3 {
+        let mut incoming = Vector3::zero();
+
+        for light in &scene.lights {
+            if light.intensity <= 0.0 {
+                continue;
+            }
+            let sample_count = light.sample_count().max(1);
+            let sample_index = rand::random::<u32>() % sample_count;
+            let light_sample = light.sample(point, sample_index, sample_count);
+            if light_sample.attenuation <= 0.0 {
+                continue;
+            }
+
+            let visibility = shadow_transmittance(point, &light_sample, scene);
+            let irradiance = light_to_irradiance(light.intensity * light_sample.attenuation);
+            incoming += light.color_vector() * visibility * (irradiance * self.phase(direction, light_sample.direction));
+        }
+
+        if let Some(environment) = &scene.environment {
+            if let Some((light_dir, weighted_radiance)) = environment.sample() {
+                let light_sample = LightSample {
+                    direction: light_dir,
+                    distance: f32::INFINITY,
+                    attenuation: 1.0,
+                };
+                let visibility = shadow_transmittance(point, &light_sample, scene);
+                incoming += weighted_radiance * visibility * self.phase(direction, light_dir);
+            }
+        }
+
+        incoming
+    }
+}
@@ -1,13 +1,25 @@
 // framebuffer.rs
 
 use raylib::prelude::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::tonemap::{encode, luminance, ToneMapping, MIDDLE_GRAY};
 
 pub struct Framebuffer {
     pub width: u32,
     pub height: u32,
     pub color_buffer: Image,
-    background_color: Color,
     current_color: Color,
+    // Suma de la radiancia lineal (HDR) de cada píxel desde el último reinicio (render progresivo)
+    accumulation: Vec<Vector3>,
+    sample_count: u32,
+    pub tone_mapping: ToneMapping,
+    /// Exposure in stops (EV): each +1 doubles the brightness before tone mapping.
+    /// With auto exposure on it acts as a compensation on top of the automatic value.
+    pub exposure: f32,
+    pub auto_exposure: bool,
 }
 
 impl Framebuffer {
@@ -17,31 +29,108 @@ impl Framebuffer {
             width,
             height,
             color_buffer,
-            background_color: Color::BLACK,
             current_color: Color::WHITE,
+            accumulation: vec![Vector3::zero(); (width * height) as usize],
+            sample_count: 0,
+            tone_mapping: ToneMapping::Aces,
+            exposure: 0.0,
+            auto_exposure: false,
         }
     }
 
-    pub fn clear(&mut self) {
-        self.color_buffer = Image::gen_image_color(self.width as i32, self.height as i32, self.background_color);
-    }
-
     pub fn set_pixel(&mut self, x: u32, y: u32) {
         if x < self.width && y < self.height {
             self.color_buffer.draw_pixel(x as i32, y as i32, self.current_color);
         }
     }
 
-    pub fn set_background_color(&mut self, color: Color) {
-        self.background_color = color;
-    }
-
     pub fn set_current_color(&mut self, color: Color) {
         self.current_color = color;
     }
 
-    pub fn _render_to_file(&self, file_path: &str) {
-        self.color_buffer.export_image(file_path);
+    pub fn add_sample(&mut self, x: u32, y: u32, color: Vector3) {
+        if x < self.width && y < self.height {
+            self.accumulation[(y * self.width + x) as usize] += color;
+        }
+    }
+
+    pub fn end_pass(&mut self) {
+        self.sample_count += 1;
+        self.resolve();
+    }
+
+    /// Rebuilds the displayed image from the HDR average with the current tone mapping,
+    /// so display settings can change without rendering again
+    pub fn resolve(&mut self) {
+        let inverse_count = 1.0 / self.sample_count.max(1) as f32;
+        let mut scale = 2.0_f32.powf(self.exposure);
+        if self.auto_exposure {
+            scale *= MIDDLE_GRAY / self.average_luminance(inverse_count);
+        }
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let average = self.accumulation[(y * self.width + x) as usize] * inverse_count;
+                self.set_current_color(encode(average * scale, self.tone_mapping));
+                self.set_pixel(x, y);
+            }
+        }
+    }
+
+    /// Log-average luminance of the image, which unlike the plain mean is not dominated
+    /// by a few very bright pixels such as light sources
+    fn average_luminance(&self, inverse_count: f32) -> f32 {
+        const DELTA: f32 = 1e-4;
+        if self.accumulation.is_empty() {
+            return MIDDLE_GRAY;
+        }
+        let log_sum: f32 = self
+            .accumulation
+            .iter()
+            .map(|&sum| {
+                let l = luminance(sum * inverse_count);
+                (DELTA + if l.is_finite() { l.max(0.0) } else { 0.0 }).ln()
+            })
+            .sum();
+        (log_sum / self.accumulation.len() as f32).exp()
+    }
+
+    pub fn reset_accumulation(&mut self) {
+        self.accumulation.fill(Vector3::zero());
+        self.sample_count = 0;
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn render_to_file(&self, file_path: &str) -> io::Result<()> {
+        let extension = Path::new(file_path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("png") => {
+                // export_image no informa de errores, así que se codifica en memoria y se escribe aparte
+                let data = self
+                    .color_buffer
+                    .export_image_to_memory(".png")
+                    .map_err(|err| io::Error::other(format!("could not encode PNG: {}", err)))?;
+                fs::write(file_path, data)
+            }
+            Some("ppm") => {
+                let mut data = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+                for color in self.color_buffer.get_image_data().iter() {
+                    data.extend_from_slice(&[color.r, color.g, color.b]);
+                }
+                fs::write(file_path, data)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unsupported image format, use .png or .ppm",
+            )),
+        }
     }
 
     pub fn swap_buffers(
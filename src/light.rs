@@ -1,17 +1,283 @@
 use raylib::prelude::*;
+use std::f32::consts::PI;
+use std::ops::Mul;
+use std::sync::Arc;
 
+use crate::ray_intersect::RayIntersect;
+use crate::sampling::stratified_sample;
+use crate::tonemap::{decode_srgb, linear_to_srgb};
+
+// Margen para que el rayo de sombra no choque con la superficie del propio emisor
+const EMITTER_BIAS: f32 = 1e-3;
+
+// Distancia por debajo de la cual la caída con el cuadrado de la distancia deja de crecer
+const MIN_DECAY_DISTANCE: f32 = 0.1;
+
+#[derive(Clone)]
+pub enum LightKind {
+    Point { position: Vector3 },
+    Directional { direction: Vector3 },
+    /// Point light restricted to a cone around `direction`; `angle` is the cone half-angle in
+    /// radians and `falloff` the fraction of the cone (0-1) over which it fades out at the edge.
+    /// A nonzero `radius` spreads the emitter over a small sphere sampled `samples` times.
+    Spot { position: Vector3, direction: Vector3, angle: f32, falloff: f32, radius: f32, samples: u32 },
+    /// Rectangle centered at `position` spanned by the full edges `edge_u` and `edge_v`
+    RectArea { position: Vector3, edge_u: Vector3, edge_v: Vector3, samples: u32 },
+    SphereArea { position: Vector3, radius: f32, samples: u32 },
+    Emissive { shape: Arc<dyn RayIntersect>, samples: u32 },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Decay {
+    Constant,
+    InverseSquare,
+}
+
+#[derive(Clone)]
 pub struct Light {
-    pub position: Vector3,
+    pub kind: LightKind,
+    pub color: Color,
+    pub intensity: f32,
+    pub decay: Decay,
+    pub range: Option<f32>,
+}
+
+pub struct AmbientLight {
     pub color: Color,
     pub intensity: f32,
+    pub samples: u32,
+    /// Only geometry closer than this occludes the ambient light
+    pub radius: f32,
+}
+
+impl AmbientLight {
+    pub fn radiance(&self) -> Vector3 {
+        decode_srgb(Vector3::new(
+            self.color.r as f32 / 255.0,
+            self.color.g as f32 / 255.0,
+            self.color.b as f32 / 255.0,
+        )) * self.intensity
+    }
+}
+
+pub struct LightSample {
+    pub direction: Vector3,
+    pub distance: f32,
+    /// Spot cone and distance attenuation, or the solid angle term of an emissive shape
+    pub attenuation: f32,
 }
 
 impl Light {
     pub fn new(position: Vector3, color: Color, intensity: f32) -> Self {
         Light {
-            position,
+            kind: LightKind::Point { position },
             color,
             intensity,
+            decay: Decay::Constant,
+            range: None,
         }
     }
-}
\ No newline at end of file
+
+    pub fn directional(direction: Vector3, color: Color, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Directional { direction: direction.normalized() },
+            color,
+            intensity,
+            decay: Decay::Constant,
+            range: None,
+        }
+    }
+
+    pub fn spot(position: Vector3, direction: Vector3, angle: f32, falloff: f32, color: Color, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Spot {
+                position,
+                direction: direction.normalized(),
+                angle,
+                falloff: falloff.clamp(0.0, 1.0),
+                radius: 0.0,
+                samples: 1,
+            },
+            color,
+            intensity,
+            decay: Decay::Constant,
+            range: None,
+        }
+    }
+
+    pub fn rect_area(position: Vector3, edge_u: Vector3, edge_v: Vector3, samples: u32, color: Color, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::RectArea { position, edge_u, edge_v, samples: samples.max(1) },
+            color,
+            intensity,
+            decay: Decay::Constant,
+            range: None,
+        }
+    }
+
+    pub fn sphere_area(position: Vector3, radius: f32, samples: u32, color: Color, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::SphereArea { position, radius, samples: samples.max(1) },
+            color,
+            intensity,
+            decay: Decay::Constant,
+            range: None,
+        }
+    }
+
+    pub fn emissive(shape: Arc<dyn RayIntersect>, samples: u32) -> Self {
+        let (color, intensity) = split_radiance(shape.material().emissive);
+        Light {
+            color,
+            intensity,
+            kind: LightKind::Emissive { shape, samples: samples.max(1) },
+            decay: Decay::Constant,
+            range: None,
+        }
+    }
+
+    /// Gives a point or spot light a spherical bulb of `radius`, sampled `samples` times so its
+    /// shadows get a penumbra. A point light with a radius is simply a sphere light.
+    pub fn with_radius(mut self, radius: f32, samples: u32) -> Self {
+        if radius <= 0.0 {
+            return self;
+        }
+        let samples = samples.max(1);
+        self.kind = match self.kind {
+            LightKind::Point { position } => LightKind::SphereArea { position, radius, samples },
+            LightKind::Spot { position, direction, angle, falloff, .. } => {
+                LightKind::Spot { position, direction, angle, falloff, radius, samples }
+            }
+            kind => kind,
+        };
+        self
+    }
+
+    pub fn color_vector(&self) -> Vector3 {
+        decode_srgb(Vector3::new(
+            self.color.r as f32 / 255.0,
+            self.color.g as f32 / 255.0,
+            self.color.b as f32 / 255.0,
+        ))
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        match self.kind {
+            LightKind::RectArea { samples, .. }
+            | LightKind::SphereArea { samples, .. }
+            | LightKind::Emissive { samples, .. }
+            | LightKind::Spot { samples, .. } => samples,
+            _ => 1,
+        }
+    }
+
+    /// Picks the `index`-th of `count` sample points on the light as seen from `point`.
+    /// Area lights are split into a grid of cells and one random point is taken per cell.
+    pub fn sample(&self, point: Vector3, index: u32, count: u32) -> LightSample {
+        let mut sample = self.sample_direction(point, index, count);
+        sample.attenuation *= self.distance_attenuation(sample.distance);
+        sample
+    }
+
+    pub fn distance_attenuation(&self, distance: f32) -> f32 {
+        if !distance.is_finite() {
+            return 1.0;
+        }
+
+        let decay = match self.decay {
+            Decay::Constant => 1.0,
+            // Se limita cerca de la luz para que no se dispare al tocarla
+            Decay::InverseSquare => 1.0 / (distance * distance).max(MIN_DECAY_DISTANCE * MIN_DECAY_DISTANCE),
+        };
+
+        // Ventana suave que llega a cero justo en el alcance, sin corte brusco
+        let window = match self.range {
+            Some(range) if range > 0.0 => (1.0 - (distance / range).powi(4)).clamp(0.0, 1.0).powi(2),
+            Some(_) => 0.0,
+            None => 1.0,
+        };
+
+        decay * window
+    }
+
+    fn sample_direction(&self, point: Vector3, index: u32, count: u32) -> LightSample {
+        match &self.kind {
+            LightKind::Point { position } => towards(point, *position),
+            LightKind::Directional { direction } => LightSample {
+                direction: -*direction,
+                distance: f32::INFINITY,
+                attenuation: 1.0,
+            },
+            LightKind::Spot { position, direction, angle, falloff, radius, .. } => {
+                let mut sample = towards(point, *position + sphere_point(index, count) * *radius);
+                let cos_theta = (-sample.direction).dot(*direction);
+                let cos_outer = angle.cos();
+                let cos_inner = (angle * (1.0 - falloff)).cos();
+                sample.attenuation = smoothstep(cos_outer, cos_inner, cos_theta);
+                sample
+            }
+            LightKind::RectArea { position, edge_u, edge_v, .. } => {
+                let (su, sv) = stratified_sample(index, count);
+                let target = *position + *edge_u * (su - 0.5) + *edge_v * (sv - 0.5);
+                towards(point, target)
+            }
+            LightKind::SphereArea { position, radius, .. } => {
+                towards(point, *position + sphere_point(index, count) * *radius)
+            }
+            LightKind::Emissive { shape, .. } => {
+                let (target, normal, area) = shape.sample_surface(point);
+                let mut sample = towards(point, target);
+                // La radiancia del emisor llega según su área visible, su inclinación y la distancia
+                let cos_light = normal.dot(-sample.direction).max(0.0);
+                let distance = sample.distance.max(MIN_DECAY_DISTANCE);
+                sample.attenuation = irradiance_to_light(area * cos_light / (distance * distance));
+                sample.distance -= EMITTER_BIAS;
+                sample
+            }
+        }
+    }
+}
+
+fn towards(point: Vector3, target: Vector3) -> LightSample {
+    let to_light = target - point;
+    LightSample {
+        direction: to_light.normalized(),
+        distance: to_light.length(),
+        attenuation: 1.0,
+    }
+}
+
+fn sphere_point(index: u32, count: u32) -> Vector3 {
+    let (su, sv) = stratified_sample(index, count);
+    let z = 1.0 - 2.0 * su;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * std::f32::consts::PI * sv;
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn split_radiance(radiance: Vector3) -> (Color, f32) {
+    let intensity = radiance.x.max(radiance.y).max(radiance.z);
+    if intensity <= 0.0 {
+        return (Color::new(0, 0, 0, 255), 0.0);
+    }
+    let channel = |c: f32| (linear_to_srgb(c / intensity) * 255.0).round() as u8;
+    (Color::new(channel(radiance.x), channel(radiance.y), channel(radiance.z), 255), intensity)
+}
+
+// Las luces no aplican el 1/π del Lambert: una superficie blanca de frente a una luz de
+// intensidad 1 refleja 1, así que su irradiancia física es π veces la intensidad
+pub fn light_to_irradiance<T: Mul<f32, Output = T>>(light: T) -> T {
+    light * PI
+}
+
+pub fn irradiance_to_light<T: Mul<f32, Output = T>>(irradiance: T) -> T {
+    irradiance * (1.0 / PI)
+}
+
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge1 <= edge0 {
+        return if x >= edge0 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
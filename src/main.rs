@@ -1,5 +1,7 @@
 use raylib::prelude::*;
 use std::f32::consts::PI;
+use std::sync::Arc;
+use std::thread::JoinHandle;
 
 mod framebuffer;
 mod ray_intersect;
@@ -10,31 +12,72 @@ mod light;
 mod material;
 mod textures;
 mod procedural;
+mod scene;
+mod bvh;
+mod render;
+mod path_tracer;
+mod sampling;
+mod tonemap;
+mod pbr;
+mod environment;
+mod fog;
+mod clouds;
+mod photon_map;
+mod sky;
 
 use framebuffer::Framebuffer;
-use ray_intersect::{Intersect, RayIntersect};
-use cube::Cube;
-use camera::Camera;
-use light::Light;
-use material::{Material, vector3_to_color};
+use ray_intersect::Intersect;
+use light::{irradiance_to_light, AmbientLight, LightKind, LightSample};
+use material::Material;
+use render::{render, Integrator, PixelFilter, RenderSettings};
+use sampling::{cosine_sample_hemisphere, sample_ggx_normal};
+use scene::Scene;
+use sky::Sky;
+use tonemap::{decode_srgb, ToneMapping};
 use textures::TextureManager;
 
 const ORIGIN_BIAS: f32 = 1e-4;
 
-fn skybox_color(dir: Vector3, texture_manager: &TextureManager) -> Vector3 {
+const DEFAULT_SCENE: &str = "scenes/skyblock.scene";
+
+// Superficies transparentes que un rayo de sombra puede atravesar antes de darse por bloqueado
+const MAX_SHADOW_CROSSINGS: u32 = 16;
+
+// Rayos por reflejo o refracción en materiales rugosos
+const GLOSSY_SAMPLES: u32 = 8;
+
+// Rebotes a partir de los cuales un reflejo ya solo ve el cielo
+const MAX_REFLECTION_DEPTH: u32 = 3;
+// Superficies que un rayo refractado puede cruzar; cada bloque transparente apilado cuesta dos
+const MAX_REFRACTION_DEPTH: u32 = 16;
+
+// Cambio de exposición por cada pulsación de +/- (en pasos EV)
+const EXPOSURE_STEP: f32 = 0.5;
+
+// Horas que avanzan o retroceden con coma y punto, y velocidad al arrancar un ciclo detenido
+const DAY_SCRUB_STEP: f32 = 0.5;
+const DAY_SPEED_STEP: f32 = 0.25;
+
+// Con el reloj en marcha el mapa de fotones solo se rehace cuando el sol avanzó este tanto
+const CAUSTICS_REBUILD_HOURS: f32 = 0.25;
+
+fn skybox_color(dir: Vector3, skybox: Option<&str>, sky: Option<&Sky>, texture_manager: &TextureManager) -> Vector3 {
     let d = dir.normalized();
+
+    if let Some(sky) = sky {
+        return sky.radiance(d) + sky.sun_disk(d) + sky.moon_disk(d);
+    }
     
     // Obtener color del skybox si existe la textura
-    if let Some(skybox_texture) = texture_manager.get_texture("assets/skybox.jpg") {
+    let skybox_texture = skybox.and_then(|path| texture_manager.get_size(path).map(|size| (path, size)));
+    if let Some((skybox_path, (width, height))) = skybox_texture {
         // Convertir dirección 3D a coordenadas UV para el skybox
         let u = 0.5 + d.x.atan2(d.z) / (2.0 * std::f32::consts::PI);
         let v = 0.5 - d.y.asin() / std::f32::consts::PI;
         
-        let width = skybox_texture.width() as u32;
-        let height = skybox_texture.height() as u32;
         let tx = (u * width as f32) as u32;
         let ty = (v * height as f32) as u32;
-        texture_manager.get_pixel_color("assets/skybox.jpg", tx, ty)
+        texture_manager.get_pixel_color(skybox_path, tx, ty)
     } else {
         // Fallback a cielo procedural estilo Skyblock
         let t = (d.y + 1.0) * 0.5;
@@ -42,7 +85,7 @@ fn skybox_color(dir: Vector3, texture_manager: &TextureManager) -> Vector3 {
         let horizon_white = Vector3::new(0.9, 0.9, 1.0);
         let cloud_white = Vector3::new(1.0, 1.0, 1.0);
         
-        if t < 0.3 {
+        let color = if t < 0.3 {
             // Horizonte
             let k = t / 0.3;
             horizon_white * (1.0 - k) + sky_blue * k
@@ -55,7 +98,17 @@ fn skybox_color(dir: Vector3, texture_manager: &TextureManager) -> Vector3 {
         } else {
             // Cielo superior
             sky_blue
-        }
+        };
+        decode_srgb(color)
+    }
+}
+
+/// Sky seen by a reflection ray. The sun and the moon already reach the surface as directional
+/// lights whose highlight stands in for their disks, so only the dome is looked up.
+fn reflected_sky_color(dir: Vector3, skybox: Option<&str>, sky: Option<&Sky>, texture_manager: &TextureManager) -> Vector3 {
+    match sky {
+        Some(sky) => sky.radiance(dir.normalized()),
+        None => skybox_color(dir, skybox, None, texture_manager),
     }
 }
 
@@ -73,7 +126,7 @@ fn reflect(incident: &Vector3, normal: &Vector3) -> Vector3 {
 }
 
 fn refract(incident: &Vector3, normal: &Vector3, refractive_index: f32) -> Option<Vector3> {
-    let mut cosi = incident.dot(*normal).max(-1.0).min(1.0);
+    let mut cosi = incident.dot(*normal).clamp(-1.0, 1.0);
     let mut etai = 1.0;
     let mut etat = refractive_index;
     let mut n = *normal;
@@ -95,61 +148,156 @@ fn refract(incident: &Vector3, normal: &Vector3, refractive_index: f32) -> Optio
     }
 }
 
-fn cast_shadow(
+fn schlick(incident: &Vector3, normal: &Vector3, refractive_index: f32) -> f32 {
+    let cosi = incident.dot(*normal).clamp(-1.0, 1.0);
+    let (etai, etat) = if cosi > 0.0 { (refractive_index, 1.0) } else { (1.0, refractive_index) };
+
+    let sint = etai / etat * (1.0 - cosi * cosi).max(0.0).sqrt();
+    if sint >= 1.0 {
+        return 1.0; // Reflexión interna total
+    }
+
+    // El ángulo se mide en el medio menos denso
+    let cos = if etai > etat { (1.0 - sint * sint).sqrt() } else { cosi.abs() };
+    let r0 = ((etai - etat) / (etai + etat)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+}
+
+/// Weights of the mirror reflection and of the refraction at a hit. `fresnel` materials split
+/// their combined reflection and transparency by the Schlick term, so they mirror more at grazing
+/// angles and let more light through head-on; other materials keep `albedo[2]` and `albedo[3]`.
+fn specular_weights(material: &Material, incident: &Vector3, normal: &Vector3) -> (f32, f32) {
+    let reflectivity = material.albedo[2];
+    let transparency = material.albedo[3];
+    if !material.fresnel {
+        return (reflectivity, transparency);
+    }
+
+    let budget = reflectivity + transparency;
+    let reflectance = schlick(incident, normal, material.refractive_index.max(1.0));
+    if transparency > 0.0 {
+        (budget * reflectance, budget * (1.0 - reflectance))
+    } else {
+        (budget * reflectance, 0.0)
+    }
+}
+
+/// Attenuation of light that reached the hit from inside the object, i.e. when the ray
+/// leaves the material through a back face after crossing it for `intersect.distance`
+fn medium_transmittance(intersect: &Intersect, ray_direction: &Vector3) -> Vector3 {
+    if ray_direction.dot(intersect.normal) > 0.0 {
+        intersect.material.transmittance(intersect.distance)
+    } else {
+        Vector3::one()
+    }
+}
+
+fn ambient_occlusion(
     intersect: &Intersect,
-    light: &Light,
-    objects: &[Cube],
+    normal: &Vector3,
+    incident: &Vector3,
+    ambient: &AmbientLight,
+    scene: &Scene,
 ) -> f32 {
-    let light_dir = (light.position - intersect.point).normalized();
-    let light_distance = (light.position - intersect.point).length();
+    let facing = if normal.dot(*incident) > 0.0 { -*normal } else { *normal };
+    let open = (0..ambient.samples)
+        .filter(|_| {
+            let direction = cosine_sample_hemisphere(facing);
+            let origin = offset_origin(intersect, &direction);
+            !scene.occluded(&origin, &direction, ambient.radius)
+        })
+        .count();
+    open as f32 / ambient.samples.max(1) as f32
+}
 
-    let shadow_ray_origin = offset_origin(intersect, &light_dir);
+fn microfacet_normal(normal: &Vector3, roughness: f32) -> Vector3 {
+    if roughness > 0.0 {
+        sample_ggx_normal(*normal, roughness * roughness)
+    } else {
+        *normal
+    }
+}
 
-    for object in objects {
-        let shadow_intersect = object.ray_intersect(&shadow_ray_origin, &light_dir);
-        if shadow_intersect.is_intersecting && shadow_intersect.distance < light_distance {
-            return 1.0;
-        }
+/// Mirror direction off a random microfacet; falls back to the sharp mirror direction when
+/// the tilted one would go into the surface
+fn glossy_reflect(intersect: &Intersect, incident: &Vector3, normal: &Vector3) -> Vector3 {
+    let facet = microfacet_normal(normal, intersect.material.surface_roughness());
+    let direction = reflect(incident, &facet).normalized();
+    if direction.dot(intersect.normal) * incident.dot(intersect.normal) > 0.0 {
+        reflect(incident, normal).normalized()
+    } else {
+        direction
     }
+}
 
-    0.0
+/// Refraction through a random microfacet, which frosts rough glass; reflects on total internal reflection
+fn glossy_refract(intersect: &Intersect, incident: &Vector3, normal: &Vector3) -> Vector3 {
+    let facet = microfacet_normal(normal, intersect.material.surface_roughness());
+    refract(incident, &facet, intersect.material.ior())
+        .unwrap_or_else(|| reflect(incident, &facet))
+        .normalized()
 }
 
-pub fn cast_ray(
-    ray_origin: &Vector3,
-    ray_direction: &Vector3,
-    objects: &[Cube],
-    light: &Light,
-    texture_manager: &TextureManager,
-    depth: u32,
-) -> Vector3 {
-    if depth > 3 {
-        return skybox_color(*ray_direction, texture_manager);
+/// Fraction of the light that reaches the hit along the shadow ray, per color channel, and
+/// whether it got there through transparent objects
+fn cast_shadow(
+    intersect: &Intersect,
+    light_sample: &LightSample,
+    scene: &Scene,
+) -> (Vector3, bool) {
+    let shadow_ray_origin = offset_origin(intersect, &light_sample.direction);
+    shadow_crossing(shadow_ray_origin, light_sample, scene)
+}
+
+/// Fraction of the light that travels from the light sample to `origin`, per color channel
+fn shadow_transmittance(origin: Vector3, light_sample: &LightSample, scene: &Scene) -> Vector3 {
+    shadow_crossing(origin, light_sample, scene).0
+}
+
+/// Opaque blockers stop the light completely; transparent ones let their transparency through,
+/// tinted by their color on the way in and by what the material absorbs on the way out.
+/// Also tells whether any transparent object was crossed.
+fn shadow_crossing(origin: Vector3, light_sample: &LightSample, scene: &Scene) -> (Vector3, bool) {
+    let light_dir = light_sample.direction;
+    let mut shadow_ray_origin = origin;
+
+    if !scene.occluded(&shadow_ray_origin, &light_dir, light_sample.distance) {
+        return (Vector3::one(), false);
     }
 
-    let mut intersect = Intersect::empty();
-    let mut zbuffer = f32::INFINITY;
+    // Recorrer los objetos entre el punto y la luz acumulando lo que deja pasar cada uno
+    let mut transmittance = Vector3::one();
+    let mut crossed = false;
+    let mut remaining = light_sample.distance;
+    for _ in 0..MAX_SHADOW_CROSSINGS {
+        let blocker = scene.closest_hit(&shadow_ray_origin, &light_dir);
+        if !blocker.is_intersecting || blocker.distance >= remaining {
+            return (transmittance, crossed);
+        }
 
-    for object in objects {
-        let i = object.ray_intersect(ray_origin, ray_direction);
-        if i.is_intersecting && i.distance < zbuffer {
-            zbuffer = i.distance;
-            intersect = i;
+        let transparency = blocker.material.transparency();
+        if transparency <= 0.0 {
+            return (Vector3::zero(), crossed);
+        }
+
+        if light_dir.dot(blocker.normal) < 0.0 {
+            transmittance *= blocker.material.diffuse * transparency;
+        } else {
+            transmittance *= medium_transmittance(&blocker, &light_dir);
         }
-    }
 
-    if !intersect.is_intersecting {
-        return skybox_color(*ray_direction, texture_manager);
+        crossed = true;
+        remaining -= blocker.distance;
+        shadow_ray_origin = offset_origin(&blocker, &light_dir);
     }
 
-    let light_dir = (light.position - intersect.point).normalized();
-    let view_dir = (*ray_origin - intersect.point).normalized();
+    (Vector3::zero(), crossed)
+}
 
+fn shading_normal(intersect: &Intersect, texture_manager: &TextureManager) -> Vector3 {
     let mut normal = intersect.normal;
-    if let Some(normal_map_path) = &intersect.material.normal_map_id {
-        let texture = texture_manager.get_texture(normal_map_path).unwrap();
-        let width = texture.width() as u32;
-        let height = texture.height() as u32;
+    let normal_map = intersect.material.normal_map_id.as_deref();
+    if let Some((normal_map_path, (width, height))) = normal_map.and_then(|path| texture_manager.get_size(path).map(|size| (path, size))) {
         let tx = (intersect.u * width as f32) as u32;
         let ty = (intersect.v * height as f32) as u32;
 
@@ -165,265 +313,493 @@ pub fn cast_ray(
         }
     }
 
-    let reflect_dir = reflect(&-light_dir, &normal).normalized();
-
-    let shadow_intensity = cast_shadow(&intersect, light, objects);
-    let light_intensity = light.intensity * (1.0 - shadow_intensity);
+    normal
+}
 
-    let diffuse_color = if let Some(texture_path) = &intersect.material.texture_id {
-        let texture = texture_manager.get_texture(texture_path).unwrap();
-        let width = texture.width() as u32;
-        let height = texture.height() as u32;
+fn surface_color(intersect: &Intersect, texture_manager: &TextureManager) -> Vector3 {
+    let texture = intersect.material.texture_id.as_deref();
+    if let Some((texture_path, (width, height))) = texture.and_then(|path| texture_manager.get_size(path).map(|size| (path, size))) {
         let tx = (intersect.u * width as f32) as u32;
         let ty = (intersect.v * height as f32) as u32;
-        let color = texture_manager.get_pixel_color(texture_path, tx, ty);
-        color
+        texture_manager.get_pixel_color(texture_path, tx, ty)
     } else {
         intersect.material.diffuse
-    };
-
-    let diffuse_intensity = normal.dot(light_dir).max(0.0) * light_intensity;
-    let diffuse = diffuse_color * diffuse_intensity;
+    }
+}
 
-    let specular_intensity = view_dir.dot(reflect_dir).max(0.0).powf(intersect.material.specular) * light_intensity;
-    let light_color_v3 = Vector3::new(light.color.r as f32 / 255.0, light.color.g as f32 / 255.0, light.color.b as f32 / 255.0);
-    let specular = light_color_v3 * specular_intensity;
+/// One light sample as it reaches the hit point
+struct LightArrival {
+    direction: Vector3,
+    /// Already includes the light's color, intensity, cone falloff and shadowing
+    radiance: Vector3,
+    /// The sky and emissive objects, which reflected rays can also hit
+    visible: bool,
+    /// Crossed glass on its way, which the photon map already brings to diffuse surfaces
+    caustic: bool,
+}
 
-    let albedo = intersect.material.albedo;
-    let phong_color = diffuse * albedo[0] + specular * albedo[1] + intersect.material.emissive;
+/// Calls `shade` for every light sample that reaches the hit point, including the sky when it
+/// lights the scene
+fn for_each_light_sample(intersect: &Intersect, scene: &Scene, mut shade: impl FnMut(&LightArrival)) {
+    for light in &scene.lights {
+        // Las luces apagadas (el sol de noche) no necesitan rayos de sombra
+        if light.intensity <= 0.0 {
+            continue;
+        }
+        let light_color = light.color_vector();
+        let sample_count = light.sample_count();
+        let visible = matches!(light.kind, LightKind::Emissive { .. });
+
+        // Las luces de área se muestrean varias veces para obtener sombras suaves
+        for sample_index in 0..sample_count {
+            let light_sample = light.sample(intersect.point, sample_index, sample_count);
+            if light_sample.attenuation <= 0.0 {
+                continue;
+            }
+
+            let (transmittance, through_glass) = cast_shadow(intersect, &light_sample, scene);
+            let light_intensity = light.intensity * light_sample.attenuation / sample_count as f32;
+            shade(&LightArrival {
+                direction: light_sample.direction,
+                radiance: light_color * transmittance * light_intensity,
+                visible,
+                caustic: through_glass && scene.caustics.is_some(),
+            });
+        }
+    }
 
-    let reflectivity = intersect.material.albedo[2];
-    let reflect_color = if reflectivity > 0.0 {
-        let reflect_dir = reflect(ray_direction, &normal).normalized();
-        let reflect_origin = offset_origin(&intersect, &reflect_dir);
-        cast_ray(&reflect_origin, &reflect_dir, objects, light, texture_manager, depth + 1)
-    } else {
-        Vector3::zero()
-    };
+    if let Some(environment) = &scene.environment {
+        for _ in 0..environment.samples {
+            let Some((direction, weighted_radiance)) = environment.sample() else {
+                continue;
+            };
+            let light_sample = LightSample {
+                direction,
+                distance: f32::INFINITY,
+                attenuation: 1.0,
+            };
+            let (visibility, _) = cast_shadow(intersect, &light_sample, scene);
+            shade(&LightArrival {
+                direction,
+                radiance: irradiance_to_light(weighted_radiance * visibility / environment.samples as f32),
+                visible: true,
+                caustic: false,
+            });
+        }
+    }
+}
 
-    let transparency = intersect.material.albedo[3];
-    let refract_color = if transparency > 0.0 {
-        if let Some(refract_dir) = refract(ray_direction, &normal, intersect.material.refractive_index) {
-            let refract_origin = offset_origin(&intersect, &refract_dir);
-            cast_ray(&refract_origin, &refract_dir, objects, light, texture_manager, depth + 1)
-        } else {
-            let reflect_dir = reflect(ray_direction, &normal).normalized();
-            let reflect_origin = offset_origin(&intersect, &reflect_dir);
-            cast_ray(&reflect_origin, &reflect_dir, objects, light, texture_manager, depth + 1)
+fn direct_lighting(
+    intersect: &Intersect,
+    normal: Vector3,
+    view_dir: Vector3,
+    diffuse_color: Vector3,
+    scene: &Scene,
+) -> (Vector3, Vector3) {
+    // Cada luz aporta difuso y especular con su propio color, intensidad y sombra
+    let mut diffuse = Vector3::zero();
+    let mut specular = Vector3::zero();
+    for_each_light_sample(intersect, scene, |light| {
+        let reflect_dir = reflect(&-light.direction, &normal).normalized();
+        if !light.caustic {
+            diffuse += diffuse_color * light.radiance * normal.dot(light.direction).max(0.0);
         }
-    } else {
-        Vector3::zero()
+        specular += light.radiance * view_dir.dot(reflect_dir).max(0.0).powf(intersect.material.specular);
+    });
+
+    (diffuse, specular)
+}
+
+struct Shading {
+    normal: Vector3,
+    /// Light from the scene lights scattered towards the viewer; emission is not included
+    direct: Vector3,
+    reflection: Vector3,
+    refraction: Vector3,
+    diffuse: Vector3,
+}
+
+fn shade(
+    intersect: &Intersect,
+    ray_direction: &Vector3,
+    scene: &Scene,
+    texture_manager: &TextureManager,
+) -> Shading {
+    let mut shading = evaluate_material(intersect, ray_direction, Some(scene), texture_manager);
+    if let Some(caustics) = &scene.caustics {
+        shading.direct += shading.diffuse * caustics.gather(intersect, ray_direction);
+    }
+    shading
+}
+
+fn material_lobes(intersect: &Intersect, ray_direction: &Vector3, texture_manager: &TextureManager) -> Shading {
+    evaluate_material(intersect, ray_direction, None, texture_manager)
+}
+
+/// Evaluates the material at the hit, with the PBR model or with Phong and the `albedo` weights;
+/// the scene lights only when a scene is given
+fn evaluate_material(
+    intersect: &Intersect,
+    ray_direction: &Vector3,
+    scene: Option<&Scene>,
+    texture_manager: &TextureManager,
+) -> Shading {
+    let material = &intersect.material;
+    let normal = shading_normal(intersect, texture_manager);
+    let view_dir = -ray_direction.normalized();
+    let base_color = surface_color(intersect, texture_manager);
+
+    if let Some(pbr) = &material.pbr {
+        return pbr::shading(intersect, normal, view_dir, base_color, pbr, scene);
+    }
+
+    let (diffuse, specular) = match scene {
+        Some(scene) => direct_lighting(intersect, normal, view_dir, base_color, scene),
+        None => (Vector3::zero(), Vector3::zero()),
     };
+    let albedo = material.albedo;
+    let (reflectivity, transparency) = specular_weights(material, ray_direction, &normal);
+    let surface = 1.0 - reflectivity - transparency;
+
+    Shading {
+        normal,
+        direct: (diffuse * albedo[0] + specular * albedo[1]) * surface,
+        reflection: Vector3::one() * reflectivity,
+        refraction: Vector3::one() * transparency,
+        diffuse: base_color * (albedo[0] * surface),
+    }
+}
 
-    phong_color * (1.0 - reflectivity - transparency) + reflect_color * reflectivity + refract_color * transparency
+pub fn cast_ray(
+    ray_origin: &Vector3,
+    ray_direction: &Vector3,
+    scene: &Scene,
+    texture_manager: &TextureManager,
+    depth: u32,
+) -> Vector3 {
+    trace_ray(ray_origin, ray_direction, scene, texture_manager, depth, false)
 }
 
-pub fn render(
-    framebuffer: &mut Framebuffer,
-    objects: &[Cube],
-    camera: &Camera,
-    light: &Light,
+fn trace_ray(
+    ray_origin: &Vector3,
+    ray_direction: &Vector3,
+    scene: &Scene,
     texture_manager: &TextureManager,
-) {
-    let width = framebuffer.width as f32;
-    let height = framebuffer.height as f32;
-    let aspect_ratio = width / height;
-    let fov = PI / 3.0;
-    let perspective_scale = (fov * 0.5).tan();
+    depth: u32,
+    reflected: bool,
+) -> Vector3 {
+    let skybox = scene.skybox.as_deref();
+    let sky = scene.sky.as_ref();
+    let sky_color = if reflected { reflected_sky_color } else { skybox_color };
 
-    for y in 0..framebuffer.height {
-        for x in 0..framebuffer.width {
-            let screen_x = (2.0 * x as f32) / width - 1.0;
-            let screen_y = -(2.0 * y as f32) / height + 1.0;
+    if depth > MAX_REFRACTION_DEPTH {
+        // Un rayo que sigue dentro del vidrio tras tantas superficies ya no aporta luz
+        return Vector3::zero();
+    }
+    if reflected && depth > MAX_REFLECTION_DEPTH {
+        return sky_color(*ray_direction, skybox, sky, texture_manager);
+    }
 
-            let screen_x = screen_x * aspect_ratio * perspective_scale;
-            let screen_y = screen_y * perspective_scale;
+    let intersect = scene.closest_hit(ray_origin, ray_direction);
+    let color = if intersect.is_intersecting {
+        hit_color(&intersect, ray_direction, scene, texture_manager, depth)
+    } else {
+        sky_color(*ray_direction, skybox, sky, texture_manager)
+    };
 
-            let ray_direction = Vector3::new(screen_x, screen_y, -1.0).normalized();
-            
-            let rotated_direction = camera.basis_change(&ray_direction);
+    let (inscatter, transmittance) = march_media(ray_origin, ray_direction, &intersect, scene);
+    color * transmittance + inscatter
+}
 
-            let pixel_color_v3 = cast_ray(&camera.eye, &rotated_direction, objects, light, texture_manager, 0);
-            let pixel_color = vector3_to_color(pixel_color_v3);
+/// Light that the fog and the clouds scatter towards the ray origin before the ray reaches
+/// the hit (or leaves the scene), and the fraction of the light from behind that gets through
+fn march_media(ray_origin: &Vector3, ray_direction: &Vector3, intersect: &Intersect, scene: &Scene) -> (Vector3, f32) {
+    // Dentro de un objeto no hay niebla ni nubes
+    if intersect.is_intersecting && ray_direction.dot(intersect.normal) > 0.0 {
+        return (Vector3::zero(), 1.0);
+    }
+    let distance = if intersect.is_intersecting { intersect.distance } else { f32::INFINITY };
+
+    let mut inscatter = Vector3::zero();
+    let mut transmittance = 1.0;
+    // Las capas están ordenadas de abajo arriba; se recorren en el orden en que las cruza el rayo
+    let mut layers: Vec<_> = scene.clouds.iter().collect();
+    if ray_direction.y < 0.0 {
+        layers.reverse();
+    }
+    for layer in layers {
+        let (layer_inscatter, layer_transmittance) = layer.march(*ray_origin, *ray_direction, distance, scene);
+        inscatter += layer_inscatter * transmittance;
+        transmittance *= layer_transmittance;
+    }
+    // La niebla se aproxima como si estuviera delante de las nubes
+    if let Some(fog) = &scene.fog {
+        let (fog_inscatter, fog_transmittance) = fog.march(*ray_origin, *ray_direction, distance, scene);
+        inscatter = inscatter * fog_transmittance + fog_inscatter;
+        transmittance *= fog_transmittance;
+    }
+    (inscatter, transmittance)
+}
 
-            framebuffer.set_current_color(pixel_color);
-            framebuffer.set_pixel(x, y);
+fn hit_color(
+    intersect: &Intersect,
+    ray_direction: &Vector3,
+    scene: &Scene,
+    texture_manager: &TextureManager,
+    depth: u32,
+) -> Vector3 {
+    let shading = shade(intersect, ray_direction, scene, texture_manager);
+    let normal = shading.normal;
+
+    // Los materiales rugosos promedian varios rayos, solo en el primer rebote para no multiplicar el costo
+    let samples = if intersect.material.surface_roughness() > 0.0 && depth == 0 { GLOSSY_SAMPLES } else { 1 };
+
+    let mut reflect_color = Vector3::zero();
+    let mut refract_color = Vector3::zero();
+    for _ in 0..samples {
+        if max_component(shading.reflection) > 0.0 {
+            let reflect_dir = glossy_reflect(intersect, ray_direction, &normal);
+            let reflect_origin = offset_origin(intersect, &reflect_dir);
+            reflect_color += trace_ray(&reflect_origin, &reflect_dir, scene, texture_manager, depth + 1, true);
+        }
+        if max_component(shading.refraction) > 0.0 {
+            let refract_dir = glossy_refract(intersect, ray_direction, &normal);
+            let refract_origin = offset_origin(intersect, &refract_dir);
+            refract_color += cast_ray(&refract_origin, &refract_dir, scene, texture_manager, depth + 1);
         }
     }
+    reflect_color /= samples as f32;
+    refract_color /= samples as f32;
+
+    // El path tracer ya calcula la luz indirecta; el ambiente solo la aproxima en el trazador clásico
+    let ambient_color = match &scene.ambient {
+        Some(ambient) => {
+            shading.diffuse * ambient.radiance() * ambient_occlusion(intersect, &normal, ray_direction, ambient, scene)
+        }
+        None => Vector3::zero(),
+    };
+
+    let color = shading.direct
+        + ambient_color
+        + intersect.material.emissive
+        + reflect_color * shading.reflection
+        + refract_color * shading.refraction;
+    color * medium_transmittance(intersect, ray_direction)
 }
 
-fn main() {
-    let window_width = 1300;
-    let window_height = 900;
- 
-    let (mut window, thread) = raylib::init()
-        .size(window_width, window_height)
-        .title("Raytracer Example")
-        .log_level(TraceLogLevel::LOG_WARNING)
-        .build();
+fn max_component(v: Vector3) -> f32 {
+    v.x.max(v.y).max(v.z)
+}
 
-    let mut texture_manager = TextureManager::new();
-    // Cargar texturas para la isla Skyblock
-    texture_manager.load_texture(&mut window, &thread, "assets/wood.jpg");
-    texture_manager.load_texture(&mut window, &thread, "assets/leaves.jpg");
-    texture_manager.load_texture(&mut window, &thread, "assets/water.jpg");
-    texture_manager.load_texture(&mut window, &thread, "assets/stone.jpg");
-    texture_manager.load_texture(&mut window, &thread, "assets/dirt.jpg");
-    texture_manager.load_texture(&mut window, &thread, "assets/glass.jpg");
-    // Cargar skybox si existe, sino usar fallback procedural
-    if std::path::Path::new("assets/skybox.jpg").exists() {
-        texture_manager.load_texture(&mut window, &thread, "assets/skybox.jpg");
-    }
-    let mut framebuffer = Framebuffer::new(window_width as u32, window_height as u32);
-
-    // Material 1: Madera (wood.jpg) - Tronco del árbol
-    let wood = Material::new(
-        Vector3::new(0.6, 0.4, 0.2), // Color marrón
-        5.0, // Specular bajo
-        [0.8, 0.1, 0.0, 0.0], // Albedo: difuso alto, specular bajo, sin reflexión ni transparencia
-        0.0, // Sin refracción
-        Some("assets/wood.jpg".to_string()),
-        None,
-        Vector3::zero(),
-    );
+struct Options {
+    scene_path: String,
+    settings: RenderSettings,
+    tone_mapping: ToneMapping,
+    exposure: f32,
+    auto_exposure: bool,
+    // Si se indica, se renderiza sin ventana y se guarda la imagen en este archivo
+    output: Option<String>,
+    width: u32,
+    height: u32,
+}
 
-    // Material 2: Hojas (leaves.jpg) - Copa del árbol
-    let leaves = Material::new(
-        Vector3::new(0.2, 0.6, 0.2), // Color verde
-        3.0, // Specular muy bajo
-        [0.9, 0.05, 0.0, 0.0], // Albedo: difuso muy alto, specular muy bajo
-        0.0, // Sin refracción
-        Some("assets/leaves.jpg".to_string()),
-        None,
-        Vector3::zero(),
-    );
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("usage: computer-graphics-v3 [scene file] [--threads N] [--integrator whitted|path] [--spp N] [--filter box|tent|gaussian]");
+    eprintln!("       [--tonemap clamp|reinhard|aces] [--exposure EV] [--auto-exposure]");
+    eprintln!("       [--output image.png|image.ppm] [--width W] [--height H]");
+    std::process::exit(2);
+}
 
-    // Material 3: Agua (water.jpg) - CON REFLEXIÓN
-    let water = Material::new(
-        Vector3::new(0.2, 0.4, 0.8), // Color azul agua
-        50.0, // Specular medio
-        [0.2, 0.1, 0.7, 0.0], // Albedo: difuso bajo, specular bajo, reflexión alta
-        0.0, // Sin refracción
-        Some("assets/water.jpg".to_string()),
-        None,
-        Vector3::zero(),
-    );
+fn parse_args() -> Options {
+    let mut options = Options {
+        scene_path: DEFAULT_SCENE.to_string(),
+        settings: RenderSettings::default(),
+        tone_mapping: ToneMapping::Aces,
+        exposure: 0.0,
+        auto_exposure: false,
+        output: None,
+        width: 1300,
+        height: 900,
+    };
 
-    // Material 4: Piedra (stone.jpg)
-    let stone = Material::new(
-        Vector3::new(0.5, 0.5, 0.5), // Color gris
-        10.0, // Specular medio
-        [0.9, 0.05, 0.0, 0.0], // Albedo: difuso muy alto, specular muy bajo
-        0.0, // Sin refracción
-        Some("assets/stone.jpg".to_string()),
-        None,
-        Vector3::zero(),
-    );
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threads" => {
+                let value = args.next().unwrap_or_else(|| usage_error("--threads needs a value"));
+                // 0 significa usar todos los núcleos disponibles
+                options.settings.threads = match value.parse::<usize>() {
+                    Ok(0) => RenderSettings::default().threads,
+                    Ok(threads) => threads,
+                    Err(_) => usage_error(&format!("invalid thread count '{}'", value)),
+                };
+            }
+            "--integrator" => {
+                let value = args.next().unwrap_or_else(|| usage_error("--integrator needs a value"));
+                options.settings.integrator = match value.as_str() {
+                    "whitted" => Integrator::Whitted,
+                    "path" => Integrator::PathTracing,
+                    _ => usage_error(&format!("unknown integrator '{}'", value)),
+                };
+            }
+            "--spp" => {
+                let value = args.next().unwrap_or_else(|| usage_error("--spp needs a value"));
+                options.settings.samples_per_pixel = match value.parse::<u32>() {
+                    Ok(samples) if samples > 0 => samples,
+                    _ => usage_error(&format!("invalid sample count '{}'", value)),
+                };
+            }
+            "--filter" => {
+                let value = args.next().unwrap_or_else(|| usage_error("--filter needs a value"));
+                options.settings.filter = match value.as_str() {
+                    "box" => PixelFilter::Box,
+                    "tent" => PixelFilter::Tent,
+                    "gaussian" => PixelFilter::Gaussian,
+                    _ => usage_error(&format!("unknown filter '{}'", value)),
+                };
+            }
+            "--tonemap" => {
+                let value = args.next().unwrap_or_else(|| usage_error("--tonemap needs a value"));
+                options.tone_mapping = match value.as_str() {
+                    "clamp" => ToneMapping::Clamp,
+                    "reinhard" => ToneMapping::Reinhard,
+                    "aces" => ToneMapping::Aces,
+                    _ => usage_error(&format!("unknown tone mapping '{}'", value)),
+                };
+            }
+            "--exposure" => {
+                let value = args.next().unwrap_or_else(|| usage_error("--exposure needs a value"));
+                options.exposure = match value.parse::<f32>() {
+                    Ok(exposure) if exposure.is_finite() => exposure,
+                    _ => usage_error(&format!("invalid exposure '{}'", value)),
+                };
+            }
+            "--auto-exposure" => options.auto_exposure = true,
+            "--output" => {
+                let output = args.next().unwrap_or_else(|| usage_error("--output needs a file name"));
+                let extension = std::path::Path::new(&output)
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+                if !matches!(extension.as_deref(), Some("png" | "ppm")) {
+                    usage_error(&format!("unsupported image format '{}', use .png or .ppm", output));
+                }
+                options.output = Some(output);
+            }
+            "--width" | "--height" => {
+                let value = args.next().unwrap_or_else(|| usage_error(&format!("{} needs a value", arg)));
+                let size = match value.parse::<u32>() {
+                    Ok(size) if size > 0 => size,
+                    _ => usage_error(&format!("invalid image size '{}'", value)),
+                };
+                if arg == "--width" {
+                    options.width = size;
+                } else {
+                    options.height = size;
+                }
+            }
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option '{}'", flag)),
+            _ => options.scene_path = arg,
+        }
+    }
 
-    // Material 5: Tierra (dirt.jpg)
-    let dirt = Material::new(
-        Vector3::new(0.4, 0.3, 0.2), // Color marrón oscuro
-        2.0, // Specular muy bajo
-        [0.9, 0.05, 0.0, 0.0], // Albedo: difuso muy alto, specular muy bajo
-        0.0, // Sin refracción
-        Some("assets/dirt.jpg".to_string()),
-        None,
-        Vector3::zero(),
-    );
+    options
+}
 
-    // Material 6: Cristal (glass.jpg) - CON REFRACCIÓN
-    let glass = Material::new(
-        Vector3::new(0.6, 0.7, 0.8), // Color azul claro
-        125.0, // Specular muy alto
-        [0.0, 0.1, 0.1, 0.8], // Albedo: sin difuso, specular bajo, reflexión baja, transparencia alta
-        1.5, // Índice de refracción del vidrio
-        Some("assets/glass.jpg".to_string()),
-        None,
-        Vector3::zero(),
+fn render_offline(
+    scene: &Scene,
+    texture_manager: &TextureManager,
+    settings: &RenderSettings,
+    framebuffer: &mut Framebuffer,
+    output: &str,
+) {
+    let start = std::time::Instant::now();
+    render(framebuffer, scene, texture_manager, settings);
+    println!(
+        "Rendered {}x{} at {} spp in {:.2} s",
+        framebuffer.width,
+        framebuffer.height,
+        settings.samples_per_pixel,
+        start.elapsed().as_secs_f64(),
     );
 
-    // Material para la luz
-    let light_material = Material::new(
-        Vector3::new(1.0, 1.0, 1.0),
-        10.0,
-        [1.0, 0.0, 0.0, 0.0],
-        0.0,
-        None,
-        None,
-        Vector3::new(1.0, 1.0, 1.0) * 2.0,
-    );
+    if let Err(err) = framebuffer.render_to_file(output) {
+        eprintln!("{}: {}", output, err);
+        std::process::exit(1);
+    }
+    println!("Saved {}", output);
+}
 
-    // Crear isla Skyblock con cubos texturizados
-    let objects = vec![
-        // Base de la isla - bloques de tierra
-        Cube { center: Vector3::new(0.0, -1.5, 0.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(1.0, -1.5, 0.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(-1.0, -1.5, 0.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(0.0, -1.5, 1.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(0.0, -1.5, -1.0), size: 1.0, material: dirt.clone() },
-        
-        // Capa superior de la isla - bloques de tierra
-        Cube { center: Vector3::new(0.0, -0.5, 0.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(1.0, -0.5, 0.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(-1.0, -0.5, 0.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(0.0, -0.5, 1.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(0.0, -0.5, -1.0), size: 1.0, material: dirt.clone() },
-        
-        // Superficie de la isla - bloques de tierra
-        Cube { center: Vector3::new(0.0, 0.5, 0.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(1.0, 0.5, 0.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(-1.0, 0.5, 0.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(0.0, 0.5, 1.0), size: 1.0, material: dirt.clone() },
-        Cube { center: Vector3::new(0.0, 0.5, -1.0), size: 1.0, material: dirt.clone() },
-        
-        // Árbol - Tronco de madera
-        Cube { center: Vector3::new(0.0, 1.5, 0.0), size: 1.0, material: wood.clone() },
-        Cube { center: Vector3::new(0.0, 2.5, 0.0), size: 1.0, material: wood.clone() },
-        
-        // Árbol - Hojas
-        Cube { center: Vector3::new(0.0, 3.5, 0.0), size: 1.0, material: leaves.clone() },
-        Cube { center: Vector3::new(1.0, 3.5, 0.0), size: 1.0, material: leaves.clone() },
-        Cube { center: Vector3::new(-1.0, 3.5, 0.0), size: 1.0, material: leaves.clone() },
-        Cube { center: Vector3::new(0.0, 3.5, 1.0), size: 1.0, material: leaves.clone() },
-        Cube { center: Vector3::new(0.0, 3.5, -1.0), size: 1.0, material: leaves.clone() },
-        Cube { center: Vector3::new(0.0, 4.5, 0.0), size: 1.0, material: leaves.clone() },
-        
-        // Cofre de madera
-        Cube { center: Vector3::new(1.5, 1.0, 1.5), size: 1.0, material: wood.clone() },
-        
-        // Bloques de piedra
-        Cube { center: Vector3::new(-1.5, 1.0, -1.5), size: 1.0, material: stone.clone() },
-        Cube { center: Vector3::new(-1.5, 2.0, -1.5), size: 1.0, material: stone.clone() },
-        
-        // Bloques de cristal
-        Cube { center: Vector3::new(2.0, 1.0, -1.0), size: 1.0, material: glass.clone() },
-        Cube { center: Vector3::new(2.0, 2.0, -1.0), size: 1.0, material: glass.clone() },
-        Cube { center: Vector3::new(2.0, 1.0, 0.0), size: 1.0, material: glass.clone() },
-        
-        // Bloques de agua reflectante
-        Cube { center: Vector3::new(-2.0, 1.0, 1.0), size: 1.0, material: water.clone() },
-        Cube { center: Vector3::new(-2.0, 1.0, 0.0), size: 1.0, material: water.clone() },
-        
-        // Luz (sol)
-        Cube { center: Vector3::new(0.0, 6.0, 0.0), size: 0.5, material: light_material.clone() },
-    ];
-
-    let mut camera = Camera::new(
-        Vector3::new(0.0, 2.0, 8.0), // Cámara más alejada y elevada
-        Vector3::new(0.0, 1.0, 0.0), // Mirando hacia el centro de la isla
-        Vector3::new(0.0, 1.0, 0.0),
+fn main() {
+    let Options {
+        scene_path,
+        mut settings,
+        tone_mapping,
+        exposure,
+        auto_exposure,
+        output,
+        width,
+        height,
+    } = parse_args();
+    let mut scene = match scene::load_scene(&scene_path) {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("{}: {}", scene_path, err);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "BVH: {} objects, {} nodes, built in {:.2} ms",
+        scene.objects().len(),
+        scene.bvh().node_count(),
+        scene.bvh().build_time().as_secs_f64() * 1000.0,
     );
+
+    let mut texture_manager = TextureManager::new();
+    // Cargar las texturas de la escena; el skybox es opcional y si no existe se usa el cielo procedural
+    for path in scene.texture_paths() {
+        if Some(&path) == scene.skybox.as_ref() && !std::path::Path::new(&path).exists() {
+            continue;
+        }
+        if let Err(err) = texture_manager.load_texture(&path) {
+            eprintln!("{}: {}", scene_path, err);
+            std::process::exit(1);
+        }
+    }
+    // Compartido con el hilo que recalcula el mapa de fotones
+    let texture_manager = Arc::new(texture_manager);
+    scene.update_sky(&texture_manager);
+    scene.build_caustics(&texture_manager, settings.threads);
+    if let Some(caustics) = &scene.caustics {
+        println!(
+            "Photon map: {} caustic photons, built in {:.2} ms",
+            caustics.len(),
+            caustics.build_time().as_secs_f64() * 1000.0,
+        );
+    }
+    let mut caustics_hour = scene.day_cycle.map(|cycle| cycle.hour);
+    let mut caustics_job = None;
+    let mut framebuffer = Framebuffer::new(width, height);
+    framebuffer.tone_mapping = tone_mapping;
+    framebuffer.exposure = exposure;
+    framebuffer.auto_exposure = auto_exposure;
+
+    if let Some(output) = output {
+        render_offline(&scene, &texture_manager, &settings, &mut framebuffer, &output);
+        return;
+    }
+
+    let (mut window, thread) = raylib::init()
+        .size(width as i32, height as i32)
+        .title("Raytracer Example")
+        .log_level(TraceLogLevel::LOG_WARNING)
+        .build();
+
     let rotation_speed = PI / 200.0; // Movimiento más suave
     let zoom_speed = 0.05; // Zoom más suave
 
-    let light = Light::new(
-        Vector3::new(1.0, -1.0, 5.0),
-        Color::new(255, 255, 255, 255),
-        1.5,
-    );
-
     while !window.window_should_close() {
-        let mut camera_moved = false;
+        let camera = &mut scene.camera;
         
         // Controles de cámara mejorados:
         // Flechas izquierda/derecha: rotación orbital horizontal
@@ -431,42 +807,133 @@ fn main() {
         
         if window.is_key_down(KeyboardKey::KEY_LEFT) {
             camera.orbit(rotation_speed, 0.0); // Rotación hacia la izquierda
-            camera_moved = true;
         }
         if window.is_key_down(KeyboardKey::KEY_RIGHT) {
             camera.orbit(-rotation_speed, 0.0); // Rotación hacia la derecha
-            camera_moved = true;
         }
         if window.is_key_down(KeyboardKey::KEY_UP) {
             camera.zoom(zoom_speed); // Zoom hacia adelante
-            camera_moved = true;
         }
         if window.is_key_down(KeyboardKey::KEY_DOWN) {
             camera.zoom(-zoom_speed); // Zoom hacia atrás
-            camera_moved = true;
         }
         
         // Controles adicionales con W, A, S, D para movimiento orbital
         if window.is_key_down(KeyboardKey::KEY_A) {
             camera.orbit(rotation_speed, 0.0);
-            camera_moved = true;
         }
         if window.is_key_down(KeyboardKey::KEY_D) {
             camera.orbit(-rotation_speed, 0.0);
-            camera_moved = true;
         }
         if window.is_key_down(KeyboardKey::KEY_W) {
             camera.orbit(0.0, -rotation_speed);
-            camera_moved = true;
         }
         if window.is_key_down(KeyboardKey::KEY_S) {
             camera.orbit(0.0, rotation_speed);
-            camera_moved = true;
         }
 
-        // Solo renderizar si la cámara se movió
-        if camera_moved {
-            render(&mut framebuffer, &objects, &camera, &light, &texture_manager);
+        // P: alternar entre el raytracer clásico y el path tracer
+        if window.is_key_pressed(KeyboardKey::KEY_P) {
+            settings.integrator = settings.integrator.toggled();
+            framebuffer.reset_accumulation();
+        }
+
+        // T: cambiar el tone mapping sin volver a renderizar
+        if window.is_key_pressed(KeyboardKey::KEY_T) {
+            framebuffer.tone_mapping = framebuffer.tone_mapping.next();
+            framebuffer.resolve();
+        }
+
+        // +/-: subir o bajar la exposición medio paso; X: activar la exposición automática
+        let mut exposure_changed = false;
+        if window.is_key_pressed(KeyboardKey::KEY_EQUAL) || window.is_key_pressed(KeyboardKey::KEY_KP_ADD) {
+            framebuffer.exposure += EXPOSURE_STEP;
+            exposure_changed = true;
+        }
+        if window.is_key_pressed(KeyboardKey::KEY_MINUS) || window.is_key_pressed(KeyboardKey::KEY_KP_SUBTRACT) {
+            framebuffer.exposure -= EXPOSURE_STEP;
+            exposure_changed = true;
+        }
+        if window.is_key_pressed(KeyboardKey::KEY_X) {
+            framebuffer.auto_exposure = !framebuffer.auto_exposure;
+            exposure_changed = true;
+        }
+        if exposure_changed {
+            println!(
+                "Exposure: {:+.1} EV{}",
+                framebuffer.exposure,
+                if framebuffer.auto_exposure { " (auto)" } else { "" },
+            );
+            framebuffer.resolve();
+        }
+
+        // Ciclo de día: N pausa, [ y ] cambian la velocidad, coma y punto mueven media hora
+        let mut sky_changed = false;
+        if let Some(cycle) = &mut scene.day_cycle {
+            let mut announce = false;
+            if window.is_key_pressed(KeyboardKey::KEY_N) {
+                cycle.paused = !cycle.paused;
+                announce = true;
+            }
+            if window.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) {
+                cycle.speed = if cycle.speed == 0.0 { DAY_SPEED_STEP } else { cycle.speed * 2.0 };
+                announce = true;
+            }
+            if window.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET) {
+                cycle.speed /= 2.0;
+                announce = true;
+            }
+            if window.is_key_pressed(KeyboardKey::KEY_PERIOD) {
+                cycle.scrub(DAY_SCRUB_STEP);
+                sky_changed = true;
+                announce = true;
+            }
+            if window.is_key_pressed(KeyboardKey::KEY_COMMA) {
+                cycle.scrub(-DAY_SCRUB_STEP);
+                sky_changed = true;
+                announce = true;
+            }
+            sky_changed |= cycle.advance(window.get_frame_time());
+            if announce {
+                let minutes = (cycle.hour * 60.0) as u32;
+                println!(
+                    "Time: {:02}:{:02} ({:.2} h/s{})",
+                    minutes / 60,
+                    minutes % 60,
+                    cycle.speed,
+                    if cycle.paused { ", paused" } else { "" },
+                );
+            }
+        }
+        if sky_changed {
+            scene.update_sky(&texture_manager);
+            framebuffer.reset_accumulation();
+        }
+        // El mapa de fotones se recalcula en otro hilo; mientras tanto se sigue usando el anterior
+        if let (Some(cycle), Some(built_hour)) = (scene.day_cycle, caustics_hour) {
+            let drift = (cycle.hour - built_hour).rem_euclid(24.0);
+            let drift = drift.min(24.0 - drift);
+            let running = !cycle.paused && cycle.speed != 0.0;
+            if caustics_job.is_none() && (drift >= CAUSTICS_REBUILD_HOURS || (drift > 0.0 && !running)) {
+                caustics_job = scene.rebuild_caustics(&texture_manager, settings.threads);
+                caustics_hour = Some(cycle.hour);
+            }
+        }
+        if caustics_job.as_ref().is_some_and(JoinHandle::is_finished) {
+            if let Some(Ok(caustics)) = caustics_job.take().map(JoinHandle::join) {
+                scene.caustics = Some(caustics);
+                framebuffer.reset_accumulation();
+            }
+        }
+
+        // Si la cámara se movió, la imagen acumulada ya no sirve
+        if scene.camera.is_changed() {
+            framebuffer.reset_accumulation();
+        }
+
+        // El path tracer sigue refinando la imagen mientras la cámara esté quieta
+        if settings.integrator.is_progressive() || framebuffer.sample_count() == 0 {
+            render(&mut framebuffer, &scene, &texture_manager, &settings);
         }
         
         framebuffer.swap_buffers(&mut window, &thread);
@@ -1,5 +1,27 @@
 use raylib::prelude::{Color, Vector3};
 
+/// Metallic/roughness parameters of a physically based material. Its base color is the
+/// material's `diffuse` (or texture) and its emission the material's `emissive`.
+#[derive(Clone, Copy, Debug)]
+pub struct Pbr {
+    pub metallic: f32,
+    pub roughness: f32,
+    pub transmission: f32,
+    /// Reflectance of a dielectric facing the viewer; without it, it follows from the index of refraction
+    pub reflectance: Option<f32>,
+}
+
+impl Default for Pbr {
+    fn default() -> Self {
+        Pbr {
+            metallic: 0.0,
+            roughness: 0.5,
+            transmission: 0.0,
+            reflectance: None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Material {
     pub diffuse: Vector3,
@@ -9,6 +31,12 @@ pub struct Material {
     pub texture_id: Option<String>,
     pub normal_map_id: Option<String>,
     pub emissive: Vector3,
+    /// Split the reflection and transparency weights by view angle (Schlick) instead of using them as fixed
+    pub fresnel: bool,
+    pub absorption: Vector3,
+    /// Blurs mirror reflections and refractions of Phong materials: 0 is perfectly sharp, 1 very blurry
+    pub roughness: f32,
+    pub pbr: Option<Pbr>,
 }
 
 impl Material {
@@ -29,6 +57,62 @@ impl Material {
             texture_id,
             normal_map_id,
             emissive,
+            fresnel: false,
+            absorption: Vector3::zero(),
+            roughness: 0.0,
+            pbr: None,
+        }
+    }
+
+    pub fn transmittance(&self, distance: f32) -> Vector3 {
+        Vector3::new(
+            (-self.absorption.x * distance).exp(),
+            (-self.absorption.y * distance).exp(),
+            (-self.absorption.z * distance).exp(),
+        )
+    }
+
+    /// PBR parameters of the material; Phong materials are converted so that old scenes can
+    /// be shaded with the PBR model too
+    pub fn to_pbr(&self) -> Pbr {
+        if let Some(pbr) = self.pbr {
+            return pbr;
+        }
+
+        // Equivalencia habitual entre el exponente de Blinn-Phong y el alpha de GGX: alpha = sqrt(2 / (n + 2))
+        let alpha = (2.0 / (self.specular.max(0.0) + 2.0)).sqrt();
+        // Un Phong reflectante sigue siendo dieléctrico: su reflejo pasa a ser la reflectancia frontal.
+        // Con fresnel ya reflejaba según su índice de refracción
+        let reflective = !self.fresnel && self.albedo[2] > 0.0;
+        Pbr {
+            metallic: 0.0,
+            roughness: if self.roughness > 0.0 { self.roughness } else { alpha.sqrt() },
+            transmission: self.albedo[3].clamp(0.0, 1.0),
+            reflectance: reflective.then(|| self.albedo[2].min(1.0)),
+        }
+    }
+
+    pub fn surface_roughness(&self) -> f32 {
+        self.pbr.map_or(self.roughness, |pbr| pbr.roughness)
+    }
+
+    pub fn transparency(&self) -> f32 {
+        self.pbr
+            .map_or(self.albedo[3], |pbr| pbr.transmission * (1.0 - pbr.metallic))
+    }
+
+    pub fn is_specular(&self) -> bool {
+        match self.pbr {
+            Some(pbr) => pbr.metallic > 0.0 || pbr.transmission > 0.0 || pbr.reflectance.is_some(),
+            None => self.albedo[2] > 0.0 || self.albedo[3] > 0.0,
+        }
+    }
+
+    pub fn ior(&self) -> f32 {
+        if self.refractive_index > 0.0 {
+            self.refractive_index
+        } else {
+            1.5
         }
     }
 
@@ -41,15 +125,19 @@ impl Material {
             texture_id: None,
             normal_map_id: None,
             emissive: Vector3::zero(),
+            fresnel: false,
+            absorption: Vector3::zero(),
+            roughness: 0.0,
+            pbr: None,
         }
     }
 }
 
 pub fn vector3_to_color(v: Vector3) -> Color {
     Color::new(
-        (v.x * 255.0).min(255.0) as u8,
-        (v.y * 255.0).min(255.0) as u8,
-        (v.z * 255.0).min(255.0) as u8,
+        (v.x.clamp(0.0, 1.0) * 255.0).round() as u8,
+        (v.y.clamp(0.0, 1.0) * 255.0).round() as u8,
+        (v.z.clamp(0.0, 1.0) * 255.0).round() as u8,
         255,
     )
-}
\ No newline at end of file
+}
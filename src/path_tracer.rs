@@ -0,0 +1,113 @@
+// path_tracer.rs
+
+use raylib::prelude::*;
+
+use crate::sampling::cosine_sample_hemisphere;
+use crate::scene::Scene;
+use crate::textures::TextureManager;
+use crate::tonemap::luminance;
+use crate::{glossy_reflect, glossy_refract, march_media, medium_transmittance, offset_origin, reflected_sky_color, shade, skybox_color};
+
+// Rebotes garantizados antes de empezar a aplicar ruleta rusa
+const MIN_BOUNCES: u32 = 3;
+
+/// Follows one random light path from the camera and returns the radiance it carries back.
+///
+/// Every hit gathers direct light from all the lights (next-event estimation) and then
+/// continues along one lobe of the material (mirror, refraction or a cosine-weighted diffuse
+/// direction) picked at random in proportion to the weights the Whitted tracer blends them
+/// with. Since emissive objects are already sampled as lights, their glow only counts when
+/// reached by the camera or a mirror/refraction bounce.
+pub fn trace_path(
+    ray_origin: &Vector3,
+    ray_direction: &Vector3,
+    scene: &Scene,
+    texture_manager: &TextureManager,
+    max_bounces: u32,
+) -> Vector3 {
+    let skybox = scene.skybox.as_deref();
+    let sky = scene.sky.as_ref();
+
+    let mut radiance = Vector3::zero();
+    let mut throughput = Vector3::one();
+    let mut origin = *ray_origin;
+    let mut direction = *ray_direction;
+    let mut specular_bounce = true;
+    let mut diffuse_bounce = false;
+    let mut sun_sampled = false;
+
+    for bounce in 0..=max_bounces {
+        let intersect = scene.closest_hit(&origin, &direction);
+
+        // La niebla y las nubes dispersan luz hacia el camino y atenúan lo que viene detrás
+        let (inscatter, transmittance) = march_media(&origin, &direction, &intersect, scene);
+        radiance += throughput * inscatter;
+        throughput *= transmittance;
+
+        if !intersect.is_intersecting {
+            // Tras un reflejo o un rebote difuso, el sol y la luna ya llegaron como luces direccionales
+            let sky_color = if sun_sampled { reflected_sky_color } else { skybox_color };
+            // Si el cielo ilumina la escena, tras un rebote difuso ya se contó al muestrearlo como luz
+            if specular_bounce || scene.environment.is_none() {
+                radiance += throughput * sky_color(direction, skybox, sky, texture_manager);
+            }
+            break;
+        }
+
+        // Lo que el camino recorrió dentro del material se tiñe según su absorción
+        throughput *= medium_transmittance(&intersect, &direction);
+
+        let material = &intersect.material;
+        // Las cáusticas del mapa de fotones ya traen la luz de un emisor visto a través de vidrio
+        if specular_bounce && !(diffuse_bounce && scene.caustics.is_some()) {
+            radiance += throughput * material.emissive;
+        }
+
+        // Luz directa en cada impacto (next-event estimation)
+        let shading = shade(&intersect, &direction, scene, texture_manager);
+        radiance += throughput * shading.direct;
+
+        // Se elige un solo lóbulo con probabilidad proporcional a su peso y se compensa en el throughput
+        let normal = shading.normal;
+        let reflect_weight = luminance(shading.reflection).max(0.0);
+        let refract_weight = luminance(shading.refraction).max(0.0);
+        let diffuse_weight = luminance(shading.diffuse).max(0.0);
+        let total_weight = reflect_weight + refract_weight + diffuse_weight;
+        if total_weight <= 0.0 {
+            break;
+        }
+        let choice = rand::random::<f32>() * total_weight;
+
+        if choice < reflect_weight {
+            direction = glossy_reflect(&intersect, &direction, &normal);
+            throughput = throughput * shading.reflection * (total_weight / reflect_weight);
+            specular_bounce = true;
+            sun_sampled = true;
+        } else if choice < reflect_weight + refract_weight {
+            direction = glossy_refract(&intersect, &direction, &normal);
+            throughput = throughput * shading.refraction * (total_weight / refract_weight);
+            specular_bounce = true;
+            sun_sampled = false;
+        } else {
+            let facing_normal = if normal.dot(direction) > 0.0 { -normal } else { normal };
+            direction = cosine_sample_hemisphere(facing_normal);
+            throughput = throughput * shading.diffuse * (total_weight / diffuse_weight);
+            specular_bounce = false;
+            diffuse_bounce = true;
+            sun_sampled = true;
+        }
+
+        origin = offset_origin(&intersect, &direction);
+
+        // Ruleta rusa: los caminos que ya aportan poco terminan al azar, compensando a los que siguen
+        if bounce >= MIN_BOUNCES {
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+            if survival <= 0.0 || rand::random::<f32>() > survival {
+                break;
+            }
+            throughput /= survival;
+        }
+    }
+
+    radiance
+}
@@ -0,0 +1,97 @@
+// pbr.rs
+
+use raylib::prelude::Vector3;
+use std::f32::consts::PI;
+
+use crate::light::light_to_irradiance;
+use crate::material::Pbr;
+use crate::ray_intersect::Intersect;
+use crate::scene::Scene;
+use crate::{for_each_light_sample, Shading};
+
+// Rugosidad mínima para que la distribución GGX no degenere en un pico infinito
+const MIN_ALPHA: f32 = 1e-3;
+
+pub fn base_reflectance(base_color: Vector3, pbr: &Pbr, refractive_index: f32) -> Vector3 {
+    let dielectric = pbr
+        .reflectance
+        .unwrap_or(((refractive_index - 1.0) / (refractive_index + 1.0)).powi(2));
+    Vector3::one() * dielectric * (1.0 - pbr.metallic) + base_color * pbr.metallic
+}
+
+pub fn fresnel_schlick(f0: Vector3, cos_theta: f32) -> Vector3 {
+    f0 + (Vector3::one() - f0) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// Fresnel for light reflected from the whole environment, which rough surfaces spread
+/// out instead of mirroring, so their grazing reflections are weaker
+pub fn fresnel_schlick_roughness(f0: Vector3, cos_theta: f32, roughness: f32) -> Vector3 {
+    let gloss = 1.0 - roughness;
+    let f90 = Vector3::new(gloss.max(f0.x), gloss.max(f0.y), gloss.max(f0.z));
+    f0 + (f90 - f0) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+fn ggx_distribution(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator)
+}
+
+fn smith_geometry(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0).powi(2) / 8.0;
+    let g1 = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+    g1(n_dot_v) * g1(n_dot_l)
+}
+
+/// Shades a hit with the Cook-Torrance GGX BRDF plus a Lambert diffuse lobe. Without a
+/// scene only the lobe weights are worked out and `direct` is left at zero.
+pub fn shading(
+    intersect: &Intersect,
+    normal: Vector3,
+    view_dir: Vector3,
+    base_color: Vector3,
+    pbr: &Pbr,
+    scene: Option<&Scene>,
+) -> Shading {
+    // Desde dentro de un objeto transparente se sombrea la cara interior
+    let facing = if normal.dot(view_dir) < 0.0 { -normal } else { normal };
+    let n_dot_v = facing.dot(view_dir).max(1e-4);
+    let f0 = base_reflectance(base_color, pbr, intersect.material.ior());
+    let alpha = (pbr.roughness * pbr.roughness).max(MIN_ALPHA);
+    let diffuse_weight = (1.0 - pbr.metallic) * (1.0 - pbr.transmission);
+
+    let mut direct = Vector3::zero();
+    if let Some(scene) = scene {
+        for_each_light_sample(intersect, scene, |light| {
+            let n_dot_l = facing.dot(light.direction);
+            if n_dot_l <= 0.0 {
+                return;
+            }
+
+            let half = (light.direction + view_dir).normalized();
+            let fresnel = fresnel_schlick(f0, view_dir.dot(half));
+            let specular = fresnel * ggx_distribution(facing.dot(half).max(0.0), alpha)
+                * smith_geometry(n_dot_v, n_dot_l, pbr.roughness)
+                / (4.0 * n_dot_l * n_dot_v);
+            let diffuse = (Vector3::one() - fresnel) * base_color * diffuse_weight;
+            if !light.caustic {
+                direct += diffuse * light.radiance * n_dot_l;
+            }
+
+            // El cielo y los emisores ya llegan por el rayo reflejado; sumarlos aquí los contaría dos veces
+            if !light.visible {
+                direct += specular * light_to_irradiance(light.radiance) * n_dot_l;
+            }
+        });
+    }
+
+    let reflection = fresnel_schlick_roughness(f0, n_dot_v, pbr.roughness);
+    let not_reflected = Vector3::one() - reflection;
+    Shading {
+        normal,
+        direct,
+        reflection,
+        refraction: not_reflected * ((1.0 - pbr.metallic) * pbr.transmission),
+        diffuse: not_reflected * base_color * diffuse_weight,
+    }
+}
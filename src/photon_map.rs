@@ -0,0 +1,287 @@
+// photon_map.rs
+
+use raylib::prelude::*;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::bvh::{Aabb, Bvh};
+use crate::light::Light;
+use crate::ray_intersect::{Intersect, RayIntersect};
+use crate::sampling::orthonormal_basis;
+use crate::scene::Scene;
+use crate::textures::TextureManager;
+use crate::tonemap::luminance;
+use crate::{glossy_reflect, glossy_refract, material_lobes, medium_transmittance, offset_origin};
+
+// Rebotes especulares que se siguen como máximo por fotón
+const MAX_PHOTON_BOUNCES: u32 = 8;
+
+struct Photon {
+    position: Vector3,
+    /// Direction the photon was travelling in when it landed
+    direction: Vector3,
+    power: Vector3,
+}
+
+/// Caustic photon map.
+///
+/// Photons are shot from every light towards the mirror and glass objects, followed through
+/// their reflections and refractions, and stored where they land on a diffuse surface after
+/// at least one of those bounces. Shading a point then adds the light of the photons stored
+/// around it, which draws the bright patterns that glass and water focus onto the ground.
+pub struct PhotonMap {
+    /// Photons shot towards each mirror or glass object, shared among the lights
+    pub photon_count: u32,
+    pub radius: f32,
+    photons: Vec<Photon>,
+    // Rejilla con celdas del tamaño del radio: basta mirar la celda del punto y sus vecinas
+    grid: HashMap<(i32, i32, i32), Vec<usize>>,
+    build_time: Duration,
+}
+
+impl PhotonMap {
+    pub fn new(photon_count: u32, radius: f32) -> Self {
+        PhotonMap {
+            photon_count: photon_count.max(1),
+            radius: radius.max(1e-3),
+            photons: Vec::new(),
+            grid: HashMap::new(),
+            build_time: Duration::ZERO,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn build_time(&self) -> Duration {
+        self.build_time
+    }
+
+    /// Shoots the photons and stores the caustic ones; has to run again whenever the lights move
+    pub fn build(&mut self, source: &PhotonSource, texture_manager: &TextureManager, threads: usize) {
+        let start = Instant::now();
+        self.photons.clear();
+        self.grid.clear();
+
+        let targets: Vec<&dyn RayIntersect> = source
+            .objects
+            .iter()
+            .filter(|object| object.material().is_specular())
+            .map(|object| object.as_ref())
+            .collect();
+        let lights = &source.lights;
+        if targets.is_empty() || lights.is_empty() {
+            self.build_time = start.elapsed();
+            return;
+        }
+
+        // Los fotones de luces direccionales salen desde fuera de toda la escena
+        let bounds = source.objects.iter().fold(Aabb::empty(), |bounds, object| bounds.union(&object.bounding_box()));
+        let far = (bounds.max - bounds.min).length();
+
+        let per_target = (self.photon_count as usize / lights.len()).max(1);
+        let threads = threads.max(1);
+        let shoot = |thread: usize| {
+            let mut photons = Vec::new();
+            let count = per_target / threads + usize::from(thread < per_target % threads);
+            for light in lights {
+                for &target in &targets {
+                    for _ in 0..count {
+                        shoot_photon(light, target, per_target, far, source, texture_manager, &mut photons);
+                    }
+                }
+            }
+            photons
+        };
+        self.photons = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..threads).map(|thread| s.spawn(move || shoot(thread))).collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("photon thread panicked"))
+                .collect()
+        });
+
+        for (index, photon) in self.photons.iter().enumerate() {
+            self.grid.entry(self.cell(photon.position)).or_default().push(index);
+        }
+        self.build_time = start.elapsed();
+    }
+
+    /// Builds a new map with the same settings on another thread, so the current one can keep
+    /// shading frames until the new one is ready
+    pub fn rebuild_in_background(
+        &self,
+        source: PhotonSource,
+        texture_manager: Arc<TextureManager>,
+        threads: usize,
+    ) -> JoinHandle<PhotonMap> {
+        let mut caustics = PhotonMap::new(self.photon_count, self.radius);
+        std::thread::spawn(move || {
+            caustics.build(&source, &texture_manager, threads);
+            caustics
+        })
+    }
+
+    fn cell(&self, point: Vector3) -> (i32, i32, i32) {
+        (
+            (point.x / self.radius).floor() as i32,
+            (point.y / self.radius).floor() as i32,
+            (point.z / self.radius).floor() as i32,
+        )
+    }
+
+    /// Caustic light arriving at the hit, to be multiplied by the surface's diffuse reflectance
+    pub fn gather(&self, intersect: &Intersect, ray_direction: &Vector3) -> Vector3 {
+        if self.photons.is_empty() {
+            return Vector3::zero();
+        }
+
+        // Solo cuentan los fotones que llegan por el lado de la superficie que se está viendo
+        let facing = if ray_direction.dot(intersect.normal) > 0.0 { -intersect.normal } else { intersect.normal };
+        let (x, y, z) = self.cell(intersect.point);
+
+        let mut flux = Vector3::zero();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(indices) = self.grid.get(&(x + dx, y + dy, z + dz)) else {
+                        continue;
+                    };
+                    for photon in indices.iter().map(|&index| &self.photons[index]) {
+                        let offset = photon.position - intersect.point;
+                        let distance = offset.length();
+                        // Se descartan los fotones de otras caras, como las de la vuelta de una esquina
+                        if distance >= self.radius
+                            || photon.direction.dot(facing) >= 0.0
+                            || offset.dot(facing).abs() > self.radius * 0.25
+                        {
+                            continue;
+                        }
+                        flux += photon.power * (1.0 - distance / self.radius);
+                    }
+                }
+            }
+        }
+
+        // Filtro cónico: los fotones cercanos pesan más, y el 3 compensa su peso medio de 1/3
+        flux * (3.0 / (PI * self.radius * self.radius))
+    }
+}
+
+/// Copy of the scene's lit lights and objects, so photons can be shot while the scene
+/// itself keeps changing
+pub struct PhotonSource {
+    lights: Vec<Light>,
+    objects: Vec<Arc<dyn RayIntersect>>,
+    bvh: Bvh,
+}
+
+impl PhotonSource {
+    pub fn new(scene: &Scene) -> Self {
+        let objects = scene.objects().to_vec();
+        PhotonSource {
+            lights: scene.lights.iter().filter(|light| light.intensity > 0.0).cloned().collect(),
+            bvh: Bvh::build(&objects),
+            objects,
+        }
+    }
+
+    fn closest_hit(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        self.bvh.closest_hit(&self.objects, ray_origin, ray_direction)
+    }
+}
+
+/// Shoots one photon from `light` through a random point of the disk that covers `target`
+/// as seen from the light, adding the caustic photons it leaves behind to `photons`
+fn shoot_photon(
+    light: &Light,
+    target: &dyn RayIntersect,
+    per_target: usize,
+    far: f32,
+    source: &PhotonSource,
+    texture_manager: &TextureManager,
+    photons: &mut Vec<Photon>,
+) {
+    let sample_count = light.sample_count().max(1);
+    let random_index = || rand::random::<u32>() % sample_count;
+
+    let bounds = target.bounding_box();
+    let center = bounds.centroid();
+    let radius = (bounds.max - bounds.min).length() * 0.5;
+    let (u, v) = orthonormal_basis(light.sample(center, random_index(), sample_count).direction);
+    let angle = rand::random::<f32>() * 2.0 * PI;
+    let offset = radius * rand::random::<f32>().sqrt();
+    let through = center + (u * angle.cos() + v * angle.sin()) * offset;
+
+    let light_sample = light.sample(through, random_index(), sample_count);
+    if light_sample.attenuation <= 0.0 {
+        return;
+    }
+
+    // Cada fotón lleva la parte de la luz que cruza el disco que le toca
+    let origin = through + light_sample.direction * light_sample.distance.min(far);
+    let direction = -light_sample.direction;
+
+    // Los discos de objetos vecinos se solapan: el fotón solo vale si lo primero que toca es su objetivo
+    let aimed = target.ray_intersect(&origin, &direction);
+    let first = source.closest_hit(&origin, &direction);
+    if !aimed.is_intersecting || !first.is_intersecting || first.distance < aimed.distance - 1e-3 {
+        return;
+    }
+    let disk_area = PI * radius * radius;
+    let power = light.color_vector() * (light.intensity * light_sample.attenuation * disk_area / per_target as f32);
+    trace_photon(origin, direction, power, source, texture_manager, photons);
+}
+
+fn trace_photon(
+    origin: Vector3,
+    direction: Vector3,
+    power: Vector3,
+    source: &PhotonSource,
+    texture_manager: &TextureManager,
+    photons: &mut Vec<Photon>,
+) {
+    let mut origin = origin;
+    let mut direction = direction;
+    let mut power = power;
+
+    for bounce in 0..MAX_PHOTON_BOUNCES {
+        let intersect = source.closest_hit(&origin, &direction);
+        if !intersect.is_intersecting {
+            return;
+        }
+
+        power *= medium_transmittance(&intersect, &direction);
+        let lobes = material_lobes(&intersect, &direction, texture_manager);
+
+        // La luz directa ya ilumina lo que el fotón alcanza sin rebotes
+        if bounce > 0 && luminance(lobes.diffuse) > 0.0 {
+            photons.push(Photon {
+                position: intersect.point,
+                direction,
+                power,
+            });
+        }
+
+        // El fotón sigue por uno de los lóbulos especulares, elegido según su peso
+        let reflect_weight = luminance(lobes.reflection).max(0.0);
+        let refract_weight = luminance(lobes.refraction).max(0.0);
+        let total_weight = reflect_weight + refract_weight;
+        if total_weight <= 0.0 {
+            return;
+        }
+
+        if rand::random::<f32>() * total_weight < reflect_weight {
+            direction = glossy_reflect(&intersect, &direction, &lobes.normal);
+            power *= lobes.reflection * (total_weight / reflect_weight);
+        } else {
+            direction = glossy_refract(&intersect, &direction, &lobes.normal);
+            power *= lobes.refraction * (total_weight / refract_weight);
+        }
+        origin = offset_origin(&intersect, &direction);
+    }
+}
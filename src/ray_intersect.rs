@@ -1,5 +1,6 @@
 use raylib::prelude::Vector3;
 use crate::material::Material;
+use crate::bvh::Aabb;
 
 #[derive(Clone)]
 pub struct Intersect {
@@ -45,6 +46,11 @@ impl Intersect {
     }
 }
 
-pub trait RayIntersect {
+pub trait RayIntersect: Send + Sync {
     fn ray_intersect(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect;
+    fn material(&self) -> &Material;
+    fn bounding_box(&self) -> Aabb;
+    /// Uniform random point on the part of the surface that faces `from`, with its normal and the
+    /// area of that part, used to sample emissive shapes as lights
+    fn sample_surface(&self, from: Vector3) -> (Vector3, Vector3, f32);
 }
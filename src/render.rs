@@ -0,0 +1,252 @@
+// render.rs
+
+use raylib::prelude::*;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cast_ray;
+use crate::framebuffer::Framebuffer;
+use crate::path_tracer::trace_path;
+use crate::sampling::stratified_sample;
+use crate::scene::Scene;
+use crate::textures::TextureManager;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Integrator {
+    Whitted,
+    /// Monte Carlo path tracing, one sample per pixel per pass. It converges to a slightly
+    /// biased image: paths are cut after `max_bounces`, enabled caustics come from the density
+    /// estimate of the photon map, and fog and clouds only scatter light once.
+    PathTracing,
+}
+
+impl Integrator {
+    pub fn toggled(self) -> Integrator {
+        match self {
+            Integrator::Whitted => Integrator::PathTracing,
+            Integrator::PathTracing => Integrator::Whitted,
+        }
+    }
+
+    pub fn is_progressive(self) -> bool {
+        self == Integrator::PathTracing
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PixelFilter {
+    Box,
+    Tent,
+    Gaussian,
+}
+
+impl PixelFilter {
+    /// Half-width, in pixels, of the square around the pixel center where samples are taken
+    pub fn radius(self) -> f32 {
+        match self {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent => 1.0,
+            PixelFilter::Gaussian => 1.5,
+        }
+    }
+
+    pub fn weight(self, dx: f32, dy: f32) -> f32 {
+        let radius = self.radius();
+        match self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent => (1.0 - dx.abs() / radius).max(0.0) * (1.0 - dy.abs() / radius).max(0.0),
+            PixelFilter::Gaussian => {
+                // Se resta el valor en el borde para que el peso llegue a cero sin saltos
+                let alpha = 2.0;
+                let edge = (-alpha * radius * radius).exp();
+                let gaussian = |d: f32| ((-alpha * d * d).exp() - edge).max(0.0);
+                gaussian(dx) * gaussian(dy)
+            }
+        }
+    }
+}
+
+pub struct RenderSettings {
+    pub threads: usize,
+    pub tile_size: u32,
+    pub integrator: Integrator,
+    /// Longest path the path tracer follows; Russian roulette usually ends paths much sooner
+    pub max_bounces: u32,
+    pub samples_per_pixel: u32,
+    pub filter: PixelFilter,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 32,
+            integrator: Integrator::Whitted,
+            max_bounces: 16,
+            samples_per_pixel: 1,
+            filter: PixelFilter::Box,
+        }
+    }
+}
+
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+fn split_into_tiles(width: u32, height: u32, tile_size: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(tile_size as usize) {
+        for x in (0..width).step_by(tile_size as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            });
+        }
+    }
+    tiles
+}
+
+fn primary_ray(x: f32, y: f32, width: u32, height: u32, scene: &Scene) -> Vector3 {
+    let width = width as f32;
+    let height = height as f32;
+    let aspect_ratio = width / height;
+    let fov = PI / 3.0;
+    let perspective_scale = (fov * 0.5).tan();
+
+    let screen_x = (2.0 * x) / width - 1.0;
+    let screen_y = -(2.0 * y) / height + 1.0;
+
+    let screen_x = screen_x * aspect_ratio * perspective_scale;
+    let screen_y = screen_y * perspective_scale;
+
+    let ray_direction = Vector3::new(screen_x, screen_y, -1.0).normalized();
+
+    scene.camera.basis_change(&ray_direction)
+}
+
+fn trace(direction: &Vector3, scene: &Scene, texture_manager: &TextureManager, settings: &RenderSettings) -> Vector3 {
+    let eye = &scene.camera.eye;
+    match settings.integrator {
+        Integrator::Whitted => cast_ray(eye, direction, scene, texture_manager, 0),
+        Integrator::PathTracing => trace_path(eye, direction, scene, texture_manager, settings.max_bounces),
+    }
+}
+
+/// Color of one pixel as the filter-weighted average of its samples. A single sample goes
+/// through the pixel center unless the integrator is progressive, in which case every
+/// pass jitters it so the accumulated image is anti-aliased as well.
+fn render_pixel(
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    scene: &Scene,
+    texture_manager: &TextureManager,
+    settings: &RenderSettings,
+) -> Vector3 {
+    let samples = settings.samples_per_pixel.max(1);
+    let center_x = x as f32 + 0.5;
+    let center_y = y as f32 + 0.5;
+
+    if samples == 1 && !settings.integrator.is_progressive() {
+        let direction = primary_ray(center_x, center_y, width, height, scene);
+        return trace(&direction, scene, texture_manager, settings);
+    }
+
+    let radius = settings.filter.radius();
+    let mut color = Vector3::zero();
+    let mut total_weight = 0.0;
+
+    for index in 0..samples {
+        let (su, sv) = stratified_sample(index, samples);
+        let dx = (su * 2.0 - 1.0) * radius;
+        let dy = (sv * 2.0 - 1.0) * radius;
+        let weight = settings.filter.weight(dx, dy);
+        if weight <= 0.0 {
+            continue;
+        }
+
+        let direction = primary_ray(center_x + dx, center_y + dy, width, height, scene);
+        color += trace(&direction, scene, texture_manager, settings) * weight;
+        total_weight += weight;
+    }
+
+    if total_weight > 0.0 {
+        color / total_weight
+    } else {
+        color
+    }
+}
+
+fn render_tile(
+    tile: &Tile,
+    width: u32,
+    height: u32,
+    scene: &Scene,
+    texture_manager: &TextureManager,
+    settings: &RenderSettings,
+) -> Vec<Vector3> {
+    let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+    for y in tile.y..tile.y + tile.height {
+        for x in tile.x..tile.x + tile.width {
+            pixels.push(render_pixel(x, y, width, height, scene, texture_manager, settings));
+        }
+    }
+    pixels
+}
+
+/// Renders one pass of the scene into the framebuffer, splitting the image into tiles
+/// that worker threads take one at a time until none are left. Each pass adds one
+/// sample per pixel to the framebuffer's running average.
+pub fn render(
+    framebuffer: &mut Framebuffer,
+    scene: &Scene,
+    texture_manager: &TextureManager,
+    settings: &RenderSettings,
+) {
+    let width = framebuffer.width;
+    let height = framebuffer.height;
+    let tiles = split_into_tiles(width, height, settings.tile_size.max(1));
+    let next_tile = AtomicUsize::new(0);
+
+    let worker = || {
+        let mut finished = Vec::new();
+        loop {
+            let index = next_tile.fetch_add(1, Ordering::Relaxed);
+            let Some(tile) = tiles.get(index) else {
+                break;
+            };
+            finished.push((tile, render_tile(tile, width, height, scene, texture_manager, settings)));
+        }
+        finished
+    };
+
+    let rendered_tiles = if settings.threads <= 1 {
+        worker()
+    } else {
+        std::thread::scope(|s| {
+            let handles: Vec<_> = (0..settings.threads).map(|_| s.spawn(worker)).collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("render thread panicked"))
+                .collect()
+        })
+    };
+
+    for (tile, pixels) in rendered_tiles {
+        let mut colors = pixels.into_iter();
+        for y in tile.y..tile.y + tile.height {
+            for x in tile.x..tile.x + tile.width {
+                if let Some(color) = colors.next() {
+                    framebuffer.add_sample(x, y, color);
+                }
+            }
+        }
+    }
+    framebuffer.end_pass();
+}
@@ -0,0 +1,46 @@
+// sampling.rs
+
+use raylib::prelude::Vector3;
+use std::f32::consts::PI;
+
+pub fn orthonormal_basis(normal: Vector3) -> (Vector3, Vector3) {
+    let helper = if normal.x.abs() > 0.9 {
+        Vector3::new(0.0, 1.0, 0.0)
+    } else {
+        Vector3::new(1.0, 0.0, 0.0)
+    };
+    let tangent = normal.cross(helper).normalized();
+    let bitangent = normal.cross(tangent);
+    (tangent, bitangent)
+}
+
+pub fn cosine_sample_hemisphere(normal: Vector3) -> Vector3 {
+    let phi = 2.0 * PI * rand::random::<f32>();
+    let r2 = rand::random::<f32>();
+    let r = r2.sqrt();
+
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    (tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + normal * (1.0 - r2).sqrt()).normalized()
+}
+
+/// Jittered position in [0, 1)² of cell `index` in a roughly square grid of `count` cells
+pub fn stratified_sample(index: u32, count: u32) -> (f32, f32) {
+    let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+    let rows = count.div_ceil(columns);
+    let column = index % columns;
+    let row = index / columns;
+    (
+        (column as f32 + rand::random::<f32>()) / columns as f32,
+        (row as f32 + rand::random::<f32>()) / rows as f32,
+    )
+}
+
+pub fn sample_ggx_normal(normal: Vector3, alpha: f32) -> Vector3 {
+    let u1 = rand::random::<f32>();
+    let phi = 2.0 * PI * rand::random::<f32>();
+    let theta = (alpha * (u1 / (1.0 - u1).max(1e-6)).sqrt()).atan();
+
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    (tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + normal * cos_theta).normalized()
+}
@@ -0,0 +1,926 @@
+// scene.rs
+
+use raylib::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::clouds::CloudLayer;
+use crate::cube::Cube;
+use crate::environment::EnvironmentLight;
+use crate::fog::{Fog, FogKind};
+use crate::light::{AmbientLight, Decay, Light};
+use crate::material::{Material, Pbr};
+use crate::photon_map::{PhotonMap, PhotonSource};
+use crate::procedural::generate_terrain;
+use crate::sky::{DayCycle, Sky};
+use crate::textures::TextureManager;
+use crate::ray_intersect::{Intersect, RayIntersect};
+use crate::sphere::Sphere;
+use crate::tonemap::decode_srgb;
+
+/// Shadow rays traced towards each emissive object per shaded point
+const EMITTER_SAMPLES: u32 = 8;
+
+pub struct Scene {
+    pub camera: Camera,
+    pub lights: Vec<Light>,
+    objects: Vec<Arc<dyn RayIntersect>>,
+    bvh: Bvh,
+    pub skybox: Option<String>,
+    /// Physical sky drawn instead of the skybox; its sun and moon are also in `lights`
+    pub sky: Option<Sky>,
+    pub day_cycle: Option<DayCycle>,
+    // Posición en `lights` de la luz del sol, seguida de la de la luna
+    sky_lights: Option<usize>,
+    pub ambient: Option<AmbientLight>,
+    /// Lighting from the sky; its table must be built once the skybox texture is loaded
+    pub environment: Option<EnvironmentLight>,
+    pub fog: Option<Fog>,
+    /// Cloud layers, from the lowest to the highest
+    pub clouds: Vec<CloudLayer>,
+    /// Caustics through glass and water; the map has to be built once the textures are loaded
+    pub caustics: Option<PhotonMap>,
+}
+
+impl Scene {
+    pub fn objects(&self) -> &[Arc<dyn RayIntersect>] {
+        &self.objects
+    }
+
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    pub fn closest_hit(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        self.bvh.closest_hit(&self.objects, ray_origin, ray_direction)
+    }
+
+    pub fn occluded(&self, ray_origin: &Vector3, ray_direction: &Vector3, max_distance: f32) -> bool {
+        self.bvh.any_hit(&self.objects, ray_origin, ray_direction, max_distance)
+    }
+
+    /// Brings the sky up to date with the time of day: moves the sun and the moon, updates
+    /// their lights and tabulates the environment again. Must also run once after loading,
+    /// when the skybox texture is available.
+    pub fn update_sky(&mut self, texture_manager: &TextureManager) {
+        if let Some(sky) = &mut self.sky {
+            if let Some(cycle) = &self.day_cycle {
+                sky.set_hour(cycle.hour);
+            }
+            if let Some(first) = self.sky_lights {
+                self.lights[first] = sky.sun_light();
+                self.lights[first + 1] = sky.moon_light();
+            }
+        }
+        if let Some(environment) = &mut self.environment {
+            environment.build(self.skybox.as_deref(), self.sky.as_ref(), texture_manager);
+        }
+    }
+
+    pub fn build_caustics(&mut self, texture_manager: &TextureManager, threads: usize) {
+        if let Some(mut caustics) = self.caustics.take() {
+            caustics.build(&PhotonSource::new(self), texture_manager, threads);
+            self.caustics = Some(caustics);
+        }
+    }
+
+    /// Starts building the photon map for the current lights on another thread; the one in
+    /// `caustics` stays in use until the caller swaps the finished map in
+    pub fn rebuild_caustics(&self, texture_manager: &Arc<TextureManager>, threads: usize) -> Option<JoinHandle<PhotonMap>> {
+        let caustics = self.caustics.as_ref()?;
+        Some(caustics.rebuild_in_background(PhotonSource::new(self), Arc::clone(texture_manager), threads))
+    }
+
+    pub fn texture_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = Vec::new();
+        for material in self.objects.iter().map(|object| object.material()) {
+            for path in [&material.texture_id, &material.normal_map_id].into_iter().flatten() {
+                if !paths.contains(path) {
+                    paths.push(path.clone());
+                }
+            }
+        }
+        if let Some(skybox) = &self.skybox {
+            if !paths.contains(skybox) {
+                paths.push(skybox.clone());
+            }
+        }
+        paths
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "{}", err),
+            SceneError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+pub fn load_scene(path: &str) -> Result<Scene, SceneError> {
+    let source = std::fs::read_to_string(path).map_err(SceneError::Io)?;
+    parse_scene(&source)
+}
+
+/// Parses the text scene format.
+///
+/// Each non-empty line is a directive followed by `key value...` pairs; `#` starts a comment:
+///
+/// ```text
+/// camera eye 0 2 8 center 0 1 0 up 0 1 0
+/// shading phong
+/// skybox assets/skybox.jpg
+/// sky sun 0.4 0.6 -0.5 turbidity 3 intensity 1 sun_intensity 1.5 sun_size 0.5
+/// sky time 17.5 speed 0.25 moon_intensity 0.15 stars 1
+/// light position 1 -1 5 color 255 255 255 intensity 1.5
+/// light type spot position 0 6 0 direction 0 -1 0 angle 25 falloff 0.3
+/// light position 0 4 0 radius 0.2 samples 16 decay inverse_square range 12 intensity 20
+/// light type rect position 0 6 0 edge_u 1 0 0 edge_v 0 0 1 samples 16
+/// light type directional direction -1 -2 -1 color 255 240 220
+/// ambient color 180 200 255 intensity 0.2 samples 8 radius 1.5
+/// environment intensity 1 samples 16
+/// clouds bottom -9 top -5 coverage 0.5 density 1.5 scale 4 steps 24 distance 150 seed 7
+/// caustics photons 200000 radius 0.1
+/// fog type height density 0.08 height 1 falloff 0.5 color 220 230 255 anisotropy 0.6 steps 24
+/// material dirt diffuse 0.4 0.3 0.2 specular 2 albedo 0.9 0.05 0 0 texture assets/dirt.jpg
+/// material glass albedo 0 0.1 0.1 0.8 refractive_index 1.5 fresnel absorption 0.8 0.95 0.9 density 1
+/// material gold base_color 1 0.78 0.34 metallic 1 roughness 0.3
+/// cube center 0 0.5 0 size 1 material dirt
+/// sphere center 0 3 0 radius 0.5 material dirt
+/// terrain width 8 depth 8 materials stone dirt water
+/// ```
+///
+/// Materials must be declared before the objects that use them. Objects with an
+/// `emissive` material are turned into lights as well. A material's `absorption` is the
+/// color white light keeps after crossing one unit of it, scaled by `density`.
+///
+/// Materials using `base_color`, `metallic` or `transmission` are shaded with the physically
+/// based GGX model; `shading pbr` before the materials converts the Phong ones too. `roughness`
+/// blurs reflections and refractions (brushed metal, frosted glass) in both models.
+///
+/// `sky` replaces the skybox with an analytic daylight sky whose sun, pointed to by `sun`,
+/// also lights the scene as a directional light; `sun_size` is the disk radius in degrees.
+/// With `time` (in hours) the sun and the moon follow the time of day instead, and `speed`
+/// hours go by per second while the window is open.
+///
+/// Each `clouds` line adds a layer of volumetric clouds between two heights, lit by the sun
+/// and the moon (or any directional light) and shadowed by themselves and the scene.
+///
+/// `caustics` adds a photon mapping pass so that glass and mirrors focus light onto the
+/// surfaces around them; more `photons` and a smaller `radius` give sharper caustics.
+///
+/// `fog` fills the air with a `homogeneous` or `height` medium; the lights seen through it
+/// cast light shafts, at the cost of `steps` shadow rays per light along each camera ray.
+pub fn parse_scene(source: &str) -> Result<Scene, SceneError> {
+    let mut camera = None;
+    let mut lights = Vec::new();
+    let mut objects: Vec<Arc<dyn RayIntersect>> = Vec::new();
+    let mut skybox = None;
+    let mut sky = None;
+    let mut ambient = None;
+    let mut environment = None;
+    let mut fog = None;
+    let mut clouds: Vec<CloudLayer> = Vec::new();
+    let mut caustics = None;
+    let mut materials: HashMap<String, Material> = HashMap::new();
+    let mut convert_to_pbr = false;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let content = raw_line.split('#').next().unwrap_or("");
+        let mut line = Line::new(index + 1, content);
+        let directive = match line.next_token() {
+            Some(directive) => directive,
+            None => continue,
+        };
+
+        match directive {
+            "camera" => {
+                if camera.is_some() {
+                    return Err(line.error("camera is already defined"));
+                }
+                camera = Some(parse_camera(&mut line)?);
+            }
+            "skybox" => {
+                skybox = Some(line.word("skybox path")?.to_string());
+            }
+            "sky" => {
+                if sky.is_some() {
+                    return Err(line.error("sky is already defined"));
+                }
+                sky = Some(parse_sky(&mut line)?);
+            }
+            "light" => lights.push(parse_light(&mut line)?),
+            "ambient" => {
+                if ambient.is_some() {
+                    return Err(line.error("ambient is already defined"));
+                }
+                ambient = Some(parse_ambient(&mut line)?);
+            }
+            "shading" => {
+                if !materials.is_empty() {
+                    return Err(line.error("shading must come before the materials"));
+                }
+                convert_to_pbr = match line.word("shading model")? {
+                    "phong" => false,
+                    "pbr" => true,
+                    other => return Err(line.error(&format!("unknown shading model '{}'", other))),
+                };
+            }
+            "environment" => {
+                if environment.is_some() {
+                    return Err(line.error("environment is already defined"));
+                }
+                environment = Some(parse_environment(&mut line)?);
+            }
+            "clouds" => clouds.push(parse_clouds(&mut line)?),
+            "caustics" => {
+                if caustics.is_some() {
+                    return Err(line.error("caustics are already defined"));
+                }
+                caustics = Some(parse_caustics(&mut line)?);
+            }
+            "fog" => {
+                if fog.is_some() {
+                    return Err(line.error("fog is already defined"));
+                }
+                fog = Some(parse_fog(&mut line)?);
+            }
+            "material" => {
+                let name = line.word("material name")?.to_string();
+                if materials.contains_key(&name) {
+                    return Err(line.error(&format!("material '{}' is already defined", name)));
+                }
+                let mut material = parse_material(&mut line)?;
+                if convert_to_pbr {
+                    material.pbr = Some(material.to_pbr());
+                }
+                materials.insert(name, material);
+            }
+            "cube" => objects.push(Arc::new(parse_cube(&mut line, &materials)?)),
+            "sphere" => objects.push(Arc::new(parse_sphere(&mut line, &materials)?)),
+            "terrain" => {
+                for sphere in parse_terrain(&mut line, &materials)? {
+                    objects.push(Arc::new(sphere));
+                }
+            }
+            other => return Err(line.error(&format!("unknown directive '{}'", other))),
+        }
+
+        line.finish()?;
+    }
+
+    let camera = camera.ok_or_else(|| SceneError::Parse {
+        line: source.lines().count(),
+        message: "scene has no camera".to_string(),
+    })?;
+
+    // Los objetos emisivos también iluminan la escena
+    for object in &objects {
+        let emissive = object.material().emissive;
+        if emissive.x > 0.0 || emissive.y > 0.0 || emissive.z > 0.0 {
+            lights.push(Light::emissive(Arc::clone(object), EMITTER_SAMPLES));
+        }
+    }
+
+    let mut sky_lights = None;
+    if let Some((sky, time)) = &mut sky {
+        if let Some(cycle) = time {
+            sky.set_hour(cycle.hour);
+        }
+        sky_lights = Some(lights.len());
+        lights.push(sky.sun_light());
+        lights.push(sky.moon_light());
+    }
+    let (sky, day_cycle) = match sky {
+        Some((sky, day_cycle)) => (Some(sky), day_cycle),
+        None => (None, None),
+    };
+
+    if lights.is_empty() && environment.is_none() {
+        return Err(SceneError::Parse {
+            line: source.lines().count(),
+            message: "scene needs at least one light, emissive object or environment".to_string(),
+        });
+    }
+
+    clouds.sort_by(|a, b| a.bottom.total_cmp(&b.bottom));
+    let bvh = Bvh::build(&objects);
+
+    Ok(Scene {
+        camera,
+        lights,
+        objects,
+        bvh,
+        skybox,
+        sky,
+        day_cycle,
+        sky_lights,
+        ambient,
+        environment,
+        fog,
+        clouds,
+        caustics,
+    })
+}
+
+fn parse_camera(line: &mut Line) -> Result<Camera, SceneError> {
+    let mut eye = None;
+    let mut center = None;
+    let mut up = Vector3::new(0.0, 1.0, 0.0);
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "eye" => eye = Some(line.vector3(key)?),
+            "center" => center = Some(line.vector3(key)?),
+            "up" => up = line.vector3(key)?,
+            other => return Err(line.unknown_key("camera", other)),
+        }
+    }
+
+    let eye = eye.ok_or_else(|| line.error("camera needs an 'eye' position"))?;
+    let center = center.ok_or_else(|| line.error("camera needs a 'center' point"))?;
+    Ok(Camera::new(eye, center, up))
+}
+
+fn parse_light(line: &mut Line) -> Result<Light, SceneError> {
+    let mut kind = "point";
+    let mut position = None;
+    let mut direction = None;
+    let mut color = Color::new(255, 255, 255, 255);
+    let mut intensity = 1.0;
+    let mut angle = 30.0;
+    let mut falloff = 0.2;
+    let mut edge_u = None;
+    let mut edge_v = None;
+    let mut radius = None;
+    let mut samples = 16;
+    let mut decay = Decay::Constant;
+    let mut range = None;
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "type" => kind = line.word(key)?,
+            "position" => position = Some(line.vector3(key)?),
+            "direction" => direction = Some(line.vector3(key)?),
+            "color" => color = line.color(key)?,
+            "intensity" => intensity = line.number(key)?,
+            "angle" => angle = line.number(key)?,
+            "falloff" => falloff = line.number(key)?,
+            "edge_u" => edge_u = Some(line.vector3(key)?),
+            "edge_v" => edge_v = Some(line.vector3(key)?),
+            "radius" => radius = Some(line.number(key)?),
+            "samples" => samples = line.count(key)? as u32,
+            "decay" => {
+                decay = match line.word(key)? {
+                    "constant" => Decay::Constant,
+                    "inverse_square" => Decay::InverseSquare,
+                    other => {
+                        return Err(line.error(&format!(
+                            "unknown decay '{}' (expected constant or inverse_square)",
+                            other
+                        )))
+                    }
+                }
+            }
+            "range" => range = Some(line.number(key)?),
+            other => return Err(line.unknown_key("light", other)),
+        }
+    }
+
+    let mut light = match kind {
+        "point" => Light::new(
+            position.ok_or_else(|| line.error("point light needs a 'position'"))?,
+            color,
+            intensity,
+        )
+        .with_radius(radius.unwrap_or(0.0), samples),
+        "directional" => Light::directional(
+            direction.ok_or_else(|| line.error("directional light needs a 'direction'"))?,
+            color,
+            intensity,
+        ),
+        "spot" => Light::spot(
+            position.ok_or_else(|| line.error("spot light needs a 'position'"))?,
+            direction.ok_or_else(|| line.error("spot light needs a 'direction'"))?,
+            f32::to_radians(angle),
+            falloff,
+            color,
+            intensity,
+        )
+        .with_radius(radius.unwrap_or(0.0), samples),
+        "rect" => Light::rect_area(
+            position.ok_or_else(|| line.error("rect light needs a 'position'"))?,
+            edge_u.ok_or_else(|| line.error("rect light needs an 'edge_u'"))?,
+            edge_v.ok_or_else(|| line.error("rect light needs an 'edge_v'"))?,
+            samples,
+            color,
+            intensity,
+        ),
+        "sphere" => Light::sphere_area(
+            position.ok_or_else(|| line.error("sphere light needs a 'position'"))?,
+            radius.ok_or_else(|| line.error("sphere light needs a 'radius'"))?,
+            samples,
+            color,
+            intensity,
+        ),
+        other => {
+            return Err(line.error(&format!(
+                "unknown light type '{}' (expected point, directional, spot, rect or sphere)",
+                other
+            )))
+        }
+    };
+    light.decay = decay;
+    light.range = range;
+    Ok(light)
+}
+
+fn parse_sky(line: &mut Line) -> Result<(Sky, Option<DayCycle>), SceneError> {
+    let mut sky = Sky::new(Vector3::new(0.4, 0.6, -0.5));
+    let mut hour = None;
+    let mut speed = 0.0;
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "sun" => {
+                let sun = line.vector3(key)?;
+                if sun.length() <= 0.0 {
+                    return Err(line.error("sky sun direction can't be zero"));
+                }
+                sky.sun_direction = sun.normalized();
+                sky.moon_direction = -sky.sun_direction;
+            }
+            "turbidity" => sky.turbidity = line.number(key)?,
+            "intensity" => sky.intensity = line.number(key)?,
+            "sun_intensity" => sky.sun_intensity = line.number(key)?,
+            "sun_size" => sky.sun_size = line.number(key)?.to_radians(),
+            "moon_intensity" => sky.moon_intensity = line.number(key)?,
+            "stars" => sky.stars = line.number(key)?,
+            "time" => hour = Some(line.number(key)?.rem_euclid(24.0)),
+            "speed" => speed = line.number(key)?,
+            other => return Err(line.unknown_key("sky", other)),
+        }
+    }
+
+    let day_cycle = hour.map(|hour| DayCycle {
+        hour,
+        speed,
+        paused: false,
+    });
+    Ok((sky, day_cycle))
+}
+
+fn parse_ambient(line: &mut Line) -> Result<AmbientLight, SceneError> {
+    let mut ambient = AmbientLight {
+        color: Color::new(255, 255, 255, 255),
+        intensity: 0.2,
+        samples: 8,
+        radius: 1.0,
+    };
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "color" => ambient.color = line.color(key)?,
+            "intensity" => ambient.intensity = line.number(key)?,
+            "samples" => ambient.samples = line.count(key)? as u32,
+            "radius" => ambient.radius = line.number(key)?,
+            other => return Err(line.unknown_key("ambient", other)),
+        }
+    }
+
+    Ok(ambient)
+}
+
+fn parse_environment(line: &mut Line) -> Result<EnvironmentLight, SceneError> {
+    let mut intensity = 1.0;
+    let mut samples = 16;
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "intensity" => intensity = line.number(key)?,
+            "samples" => samples = line.count(key)? as u32,
+            other => return Err(line.unknown_key("environment", other)),
+        }
+    }
+
+    Ok(EnvironmentLight::new(intensity, samples))
+}
+
+fn parse_clouds(line: &mut Line) -> Result<CloudLayer, SceneError> {
+    let mut bottom = None;
+    let mut top = None;
+    let mut coverage = None;
+    let mut density = None;
+    let mut scale = None;
+    let mut steps = None;
+    let mut distance = None;
+    let mut seed = rand::random::<u32>();
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "bottom" => bottom = Some(line.number(key)?),
+            "top" => top = Some(line.number(key)?),
+            "coverage" => coverage = Some(line.number(key)?),
+            "density" => density = Some(line.number(key)?),
+            "scale" => scale = Some(line.number(key)?),
+            "steps" => steps = Some(line.count(key)? as u32),
+            "distance" => distance = Some(line.number(key)?),
+            "seed" => seed = line.unsigned(key)?,
+            other => return Err(line.unknown_key("clouds", other)),
+        }
+    }
+
+    let bottom = bottom.ok_or_else(|| line.error("clouds need a 'bottom'"))?;
+    let top = top.ok_or_else(|| line.error("clouds need a 'top'"))?;
+    if top <= bottom {
+        return Err(line.error("clouds 'top' must be above 'bottom'"));
+    }
+
+    let mut layer = CloudLayer::new(bottom, top, seed);
+    layer.coverage = coverage.unwrap_or(layer.coverage);
+    layer.density = density.unwrap_or(layer.density);
+    layer.scale = scale.unwrap_or(layer.scale);
+    layer.steps = steps.unwrap_or(layer.steps);
+    layer.distance = distance.unwrap_or(layer.distance);
+    Ok(layer)
+}
+
+fn parse_caustics(line: &mut Line) -> Result<PhotonMap, SceneError> {
+    let mut photons = 200_000;
+    let mut radius = 0.1;
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "photons" => photons = line.count(key)? as u32,
+            "radius" => radius = line.number(key)?,
+            other => return Err(line.unknown_key("caustics", other)),
+        }
+    }
+
+    Ok(PhotonMap::new(photons, radius))
+}
+
+fn parse_fog(line: &mut Line) -> Result<Fog, SceneError> {
+    let mut kind = "homogeneous";
+    let mut height = 0.0;
+    let mut falloff = 1.0;
+    let mut fog = Fog {
+        kind: FogKind::Homogeneous,
+        density: 0.05,
+        color: Color::new(255, 255, 255, 255),
+        anisotropy: 0.3,
+        steps: 24,
+        distance: 50.0,
+    };
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "type" => kind = line.word(key)?,
+            "density" => fog.density = line.number(key)?,
+            "color" => fog.color = line.color(key)?,
+            "anisotropy" => fog.anisotropy = line.number(key)?,
+            "steps" => fog.steps = line.count(key)? as u32,
+            "distance" => fog.distance = line.number(key)?,
+            "height" => height = line.number(key)?,
+            "falloff" => falloff = line.number(key)?,
+            other => return Err(line.unknown_key("fog", other)),
+        }
+    }
+
+    fog.kind = match kind {
+        "homogeneous" => FogKind::Homogeneous,
+        "height" => FogKind::Height { height, falloff },
+        other => {
+            return Err(line.error(&format!(
+                "unknown fog type '{}' (expected homogeneous or height)",
+                other
+            )))
+        }
+    };
+    Ok(fog)
+}
+
+fn parse_material(line: &mut Line) -> Result<Material, SceneError> {
+    let mut material = Material::new(
+        Vector3::one(),
+        10.0,
+        [1.0, 0.0, 0.0, 0.0],
+        0.0,
+        None,
+        None,
+        Vector3::zero(),
+    );
+
+    // Color que conserva la luz tras recorrer una unidad dentro del material, y cuánto se intensifica
+    let mut absorption_color = None;
+    let mut density = 1.0;
+    let mut pbr: Option<Pbr> = None;
+    let mut roughness = None;
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "diffuse" => material.diffuse = decode_srgb(line.vector3(key)?),
+            "specular" => material.specular = line.number(key)?,
+            "albedo" => {
+                material.albedo = [
+                    line.number(key)?,
+                    line.number(key)?,
+                    line.number(key)?,
+                    line.number(key)?,
+                ]
+            }
+            "refractive_index" => material.refractive_index = line.number(key)?,
+            "texture" => material.texture_id = Some(line.word(key)?.to_string()),
+            "normal_map" => material.normal_map_id = Some(line.word(key)?.to_string()),
+            "emissive" => material.emissive = line.vector3(key)?,
+            "fresnel" => material.fresnel = true,
+            "absorption" => absorption_color = Some(decode_srgb(line.vector3(key)?)),
+            "density" => density = line.number(key)?,
+            // Cualquiera de estas claves convierte el material en PBR
+            "base_color" => {
+                material.diffuse = decode_srgb(line.vector3(key)?);
+                pbr.get_or_insert_with(Pbr::default);
+            }
+            "metallic" => pbr.get_or_insert_with(Pbr::default).metallic = line.number(key)?.clamp(0.0, 1.0),
+            "transmission" => pbr.get_or_insert_with(Pbr::default).transmission = line.number(key)?.clamp(0.0, 1.0),
+            "ior" => material.refractive_index = line.number(key)?,
+            "roughness" => roughness = Some(line.number(key)?.clamp(0.0, 1.0)),
+            other => return Err(line.unknown_key("material", other)),
+        }
+    }
+
+    if let Some(color) = absorption_color {
+        let coefficient = |c: f32| -c.clamp(1e-4, 1.0).ln() * density;
+        material.absorption = Vector3::new(coefficient(color.x), coefficient(color.y), coefficient(color.z));
+    }
+    // La rugosidad es del modelo PBR si el material lo usa, o desenfoca los reflejos Phong
+    if let Some(roughness) = roughness {
+        match &mut pbr {
+            Some(pbr) => pbr.roughness = roughness,
+            None => material.roughness = roughness,
+        }
+    }
+    material.pbr = pbr;
+
+    Ok(material)
+}
+
+fn parse_cube(line: &mut Line, materials: &HashMap<String, Material>) -> Result<Cube, SceneError> {
+    let mut center = None;
+    let mut size = 1.0;
+    let mut material = None;
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "center" => center = Some(line.vector3(key)?),
+            "size" => size = line.number(key)?,
+            "material" => material = Some(line.material(materials)?),
+            other => return Err(line.unknown_key("cube", other)),
+        }
+    }
+
+    Ok(Cube {
+        center: center.ok_or_else(|| line.error("cube needs a 'center'"))?,
+        size,
+        material: material.ok_or_else(|| line.error("cube needs a 'material'"))?,
+    })
+}
+
+fn parse_sphere(line: &mut Line, materials: &HashMap<String, Material>) -> Result<Sphere, SceneError> {
+    let mut center = None;
+    let mut radius = 1.0;
+    let mut material = None;
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "center" => center = Some(line.vector3(key)?),
+            "radius" => radius = line.number(key)?,
+            "material" => material = Some(line.material(materials)?),
+            other => return Err(line.unknown_key("sphere", other)),
+        }
+    }
+
+    Ok(Sphere {
+        center: center.ok_or_else(|| line.error("sphere needs a 'center'"))?,
+        radius,
+        material: material.ok_or_else(|| line.error("sphere needs a 'material'"))?,
+    })
+}
+
+fn parse_terrain(line: &mut Line, materials: &HashMap<String, Material>) -> Result<Vec<Sphere>, SceneError> {
+    let mut width = None;
+    let mut depth = None;
+    let mut terrain_materials = Vec::new();
+
+    while let Some(key) = line.next_token() {
+        match key {
+            "width" => width = Some(line.count(key)?),
+            "depth" => depth = Some(line.count(key)?),
+            // Materiales para zonas altas, medias y bajas
+            "materials" => {
+                terrain_materials = vec![
+                    line.material(materials)?,
+                    line.material(materials)?,
+                    line.material(materials)?,
+                ]
+            }
+            other => return Err(line.unknown_key("terrain", other)),
+        }
+    }
+
+    let width = width.ok_or_else(|| line.error("terrain needs a 'width'"))?;
+    let depth = depth.ok_or_else(|| line.error("terrain needs a 'depth'"))?;
+    if terrain_materials.is_empty() {
+        return Err(line.error("terrain needs three 'materials'"));
+    }
+    Ok(generate_terrain(width, depth, &terrain_materials))
+}
+
+struct Line<'a> {
+    number: usize,
+    tokens: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> Line<'a> {
+    fn new(number: usize, content: &'a str) -> Self {
+        Line {
+            number,
+            tokens: content.split_whitespace(),
+        }
+    }
+
+    fn next_token(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    fn error(&self, message: &str) -> SceneError {
+        SceneError::Parse {
+            line: self.number,
+            message: message.to_string(),
+        }
+    }
+
+    fn unknown_key(&self, directive: &str, key: &str) -> SceneError {
+        self.error(&format!("unknown {} property '{}'", directive, key))
+    }
+
+    fn word(&mut self, what: &str) -> Result<&'a str, SceneError> {
+        self.next_token()
+            .ok_or_else(|| self.error(&format!("expected a value for '{}'", what)))
+    }
+
+    fn number(&mut self, what: &str) -> Result<f32, SceneError> {
+        let token = self.word(what)?;
+        token
+            .parse::<f32>()
+            .map_err(|_| self.error(&format!("expected a number for '{}', found '{}'", what, token)))
+    }
+
+    fn count(&mut self, what: &str) -> Result<i32, SceneError> {
+        let token = self.word(what)?;
+        token
+            .parse::<i32>()
+            .ok()
+            .filter(|count| *count > 0)
+            .ok_or_else(|| self.error(&format!("expected a positive whole number for '{}', found '{}'", what, token)))
+    }
+
+    fn unsigned(&mut self, what: &str) -> Result<u32, SceneError> {
+        let token = self.word(what)?;
+        token
+            .parse::<u32>()
+            .map_err(|_| self.error(&format!("expected a whole number for '{}', found '{}'", what, token)))
+    }
+
+    fn vector3(&mut self, what: &str) -> Result<Vector3, SceneError> {
+        Ok(Vector3::new(self.number(what)?, self.number(what)?, self.number(what)?))
+    }
+
+    fn color(&mut self, what: &str) -> Result<Color, SceneError> {
+        let mut channel = || -> Result<u8, SceneError> {
+            let token = self.word(what)?;
+            token.parse::<u8>().map_err(|_| {
+                self.error(&format!("expected a 0-255 color channel for '{}', found '{}'", what, token))
+            })
+        };
+        Ok(Color::new(channel()?, channel()?, channel()?, 255))
+    }
+
+    fn material(&mut self, materials: &HashMap<String, Material>) -> Result<Material, SceneError> {
+        let name = self.word("material")?;
+        materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.error(&format!("unknown material '{}'", name)))
+    }
+
+    fn finish(&mut self) -> Result<(), SceneError> {
+        match self.next_token() {
+            Some(extra) => Err(self.error(&format!("unexpected '{}'", extra))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::LightKind;
+
+    const CAMERA: &str = "camera eye 0 2 8 center 0 1 0 up 0 1 0";
+
+    fn parse_error(source: &str) -> (usize, String) {
+        match parse_scene(source) {
+            Err(SceneError::Parse { line, message }) => (line, message),
+            Err(SceneError::Io(err)) => panic!("unexpected I/O error: {}", err),
+            Ok(_) => panic!("scene parsed without errors"),
+        }
+    }
+
+    #[test]
+    fn parses_objects_lights_and_materials() {
+        let source = format!(
+            "{}\n\
+             light position 1 4 2 color 255 128 0 intensity 1.5\n\
+             material stone diffuse 0.5 0.4 0.3 specular 20 albedo 0.8 0.2 0.1 0 refractive_index 1.5 # piedra\n\
+             cube center 0 0.5 0 size 2 material stone\n\
+             sphere center 0 3 0 radius 0.5 material stone\n",
+            CAMERA
+        );
+        let scene = match parse_scene(&source) {
+            Ok(scene) => scene,
+            Err(err) => panic!("{}", err),
+        };
+
+        assert_eq!(scene.lights.len(), 1);
+        let light = &scene.lights[0];
+        assert!(matches!(light.kind, LightKind::Point { position } if position == Vector3::new(1.0, 4.0, 2.0)));
+        assert_eq!((light.color.r, light.color.g, light.color.b), (255, 128, 0));
+        assert_eq!(light.intensity, 1.5);
+
+        assert_eq!(scene.objects.len(), 2);
+        let down = Vector3::new(0.0, -1.0, 0.0);
+        let sphere_top = scene.closest_hit(&Vector3::new(0.0, 10.0, 0.0), &down);
+        assert!(sphere_top.is_intersecting);
+        assert!((sphere_top.point.y - 3.5).abs() < 1e-4, "{}", sphere_top.point.y);
+        let cube_top = scene.closest_hit(&Vector3::new(0.9, 10.0, 0.0), &down);
+        assert!(cube_top.is_intersecting);
+        assert!((cube_top.point.y - 1.5).abs() < 1e-4, "{}", cube_top.point.y);
+
+        let material = &cube_top.material;
+        assert_eq!(material.diffuse, decode_srgb(Vector3::new(0.5, 0.4, 0.3)));
+        assert_eq!(material.specular, 20.0);
+        assert_eq!(material.albedo, [0.8, 0.2, 0.1, 0.0]);
+        assert_eq!(material.refractive_index, 1.5);
+    }
+
+    #[test]
+    fn reports_unknown_directive() {
+        let source = format!("{}\n# comentario\n\nlamp position 0 1 0\n", CAMERA);
+        let (line, message) = parse_error(&source);
+        assert_eq!(line, 4);
+        assert!(message.contains("unknown directive 'lamp'"), "{}", message);
+    }
+
+    #[test]
+    fn reports_missing_value() {
+        let source = format!("{}\nlight position 0 4 0 intensity\n", CAMERA);
+        let (line, message) = parse_error(&source);
+        assert_eq!(line, 2);
+        assert!(message.contains("expected a value for 'intensity'"), "{}", message);
+    }
+
+    #[test]
+    fn reports_unknown_key() {
+        let source = format!("{}\nlight position 0 4 0 intensity 1\nmaterial stone diffuse 0.5 0.5 0.5 shine 3\n", CAMERA);
+        let (line, message) = parse_error(&source);
+        assert_eq!(line, 3);
+        assert!(message.contains("unknown material property 'shine'"), "{}", message);
+    }
+
+    #[test]
+    fn reports_bad_values_inside_light_and_material() {
+        let source = format!("{}\nlight position 0 4 0 color 300 0 0\n", CAMERA);
+        let (line, message) = parse_error(&source);
+        assert_eq!(line, 2);
+        assert!(message.contains("0-255 color channel for 'color', found '300'"), "{}", message);
+
+        let source = format!("{}\nlight position 0 4 0\n\nmaterial stone albedo 0.9 0.1 x 0\n", CAMERA);
+        let (line, message) = parse_error(&source);
+        assert_eq!(line, 4);
+        assert!(message.contains("expected a number for 'albedo', found 'x'"), "{}", message);
+    }
+}
@@ -0,0 +1,265 @@
+// sky.rs
+
+use raylib::prelude::*;
+
+use crate::light::{smoothstep, split_radiance, Light};
+
+// Escala de la luminancia del modelo (kcd/m²) a la radiancia lineal del renderizador
+const SKY_SCALE: f32 = 0.04;
+
+// Brillo del disco solar relativo a la luz del sol; basta con que sature al verlo directamente
+const SUN_DISK_BRIGHTNESS: f32 = 20.0;
+
+// Resplandor de la luna y del cielo nocturno, muy por debajo del día
+const MOON_DISK_BRIGHTNESS: f32 = 1.5;
+const NIGHT_SKY: Vector3 = Vector3 { x: 0.0015, y: 0.0025, z: 0.006 };
+const MOONLIGHT_COLOR: Vector3 = Vector3 { x: 0.6, y: 0.7, z: 1.0 };
+
+// Celdas por unidad de dirección en la rejilla de estrellas y fracción de celdas con una
+const STAR_GRID: f32 = 150.0;
+const STAR_DENSITY: f32 = 0.04;
+
+// Latitud del observador: inclina el recorrido del sol para que al mediodía no esté en el cenit
+const LATITUDE: f32 = 30.0;
+
+// Extinción por unidad de masa de aire y de turbidez; el azul se pierde antes y el sol se enrojece
+const SUN_EXTINCTION: Vector3 = Vector3 { x: 0.02, y: 0.045, z: 0.1 };
+
+/// Analytic daylight sky (Preetham et al., "A Practical Analytic Model for Daylight").
+///
+/// The sky brightness and color come from the Perez distribution fitted to the sun position
+/// and the atmospheric turbidity, so the sky is deep blue overhead at noon, hazy with high
+/// turbidity and orange near the horizon at sunset. The same sun drives a directional light,
+/// and once it sets the sky fades into a starry night lit by the moon.
+#[derive(Clone, Debug)]
+pub struct Sky {
+    pub sun_direction: Vector3,
+    /// Haze of the atmosphere: 2 is a very clear day, 10 a hazy one
+    pub turbidity: f32,
+    pub intensity: f32,
+    pub sun_intensity: f32,
+    /// Angular radius of the visible sun disk, in radians
+    pub sun_size: f32,
+    pub moon_direction: Vector3,
+    pub moon_intensity: f32,
+    pub stars: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DayCycle {
+    pub hour: f32,
+    /// Hours that go by per second of real time
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl DayCycle {
+    /// Moves the clock forward by `seconds` of real time; returns whether the time changed
+    pub fn advance(&mut self, seconds: f32) -> bool {
+        if self.paused || self.speed == 0.0 || seconds <= 0.0 {
+            return false;
+        }
+        self.scrub(self.speed * seconds);
+        true
+    }
+
+    pub fn scrub(&mut self, hours: f32) {
+        self.hour = (self.hour + hours).rem_euclid(24.0);
+    }
+}
+
+/// Coefficients A..E of the Perez sky distribution for one channel
+struct Perez([f32; 5]);
+
+impl Perez {
+    fn evaluate(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.0;
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+    }
+}
+
+impl Sky {
+    pub fn new(sun_direction: Vector3) -> Self {
+        Sky {
+            sun_direction: sun_direction.normalized(),
+            turbidity: 3.0,
+            intensity: 1.0,
+            sun_intensity: 1.5,
+            sun_size: 0.5_f32.to_radians(),
+            moon_direction: -sun_direction.normalized(),
+            moon_intensity: 0.15,
+            stars: 1.0,
+        }
+    }
+
+    /// Places the sun and the moon for the time of day: the sun rises in the east (+x) at 6,
+    /// peaks at noon and sets in the west at 18, and the moon goes the opposite way
+    pub fn set_hour(&mut self, hour: f32) {
+        let angle = (hour - 6.0) / 24.0 * 2.0 * std::f32::consts::PI;
+        let latitude = LATITUDE.to_radians();
+        self.sun_direction = Vector3::new(
+            angle.cos(),
+            angle.sin() * latitude.cos(),
+            angle.sin() * latitude.sin(),
+        )
+        .normalized();
+        self.moon_direction = -self.sun_direction;
+    }
+
+    /// How much daylight is left: 1 with the sun up, fading to 0 once it has set
+    pub fn daylight(&self) -> f32 {
+        smoothstep(-0.1, 0.05, self.sun_direction.y)
+    }
+
+    /// Linear radiance of the sky dome seen along `direction`, without the sun disk
+    pub fn radiance(&self, direction: Vector3) -> Vector3 {
+        let daylight = self.daylight();
+        let night = self.night_radiance(direction) * (1.0 - daylight);
+        if daylight <= 0.0 {
+            return night;
+        }
+
+        let t = self.turbidity.clamp(1.7, 10.0);
+        // El modelo solo vale con el sol sobre el horizonte
+        let sun = Vector3::new(self.sun_direction.x, self.sun_direction.y.max(0.0), self.sun_direction.z).normalized();
+        let theta_sun = sun.y.clamp(-1.0, 1.0).acos();
+
+        // Debajo del horizonte se repite el color del horizonte, oscurecido como si fuera suelo
+        let view = Vector3::new(direction.x, direction.y.max(0.0), direction.z).normalized();
+        let ground = 1.0 - 0.7 * smoothstep(0.0, 0.2, -direction.y);
+        let cos_theta = view.y.max(0.01);
+        let gamma = view.dot(sun).clamp(-1.0, 1.0).acos();
+
+        let luminance = Perez([
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ]);
+        let chroma_x = Perez([
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ]);
+        let chroma_y = Perez([
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ]);
+
+        let (zenith_luminance, zenith_x, zenith_y) = zenith(t, theta_sun);
+        let relative = |perez: &Perez| perez.evaluate(cos_theta, gamma) / perez.evaluate(1.0, theta_sun);
+        let big_y = zenith_luminance * relative(&luminance);
+        let x = zenith_x * relative(&chroma_x);
+        let y = zenith_y * relative(&chroma_y);
+
+        xyy_to_rgb(x, y, big_y) * (SKY_SCALE * self.intensity * daylight * ground) + night
+    }
+
+    fn night_radiance(&self, direction: Vector3) -> Vector3 {
+        let base = NIGHT_SKY * self.intensity;
+        if direction.y <= 0.0 || self.stars <= 0.0 {
+            return base;
+        }
+
+        // Cada celda de la rejilla puede tener una estrella en un punto al azar dentro de ella
+        let point = direction.normalized() * STAR_GRID;
+        let cell = Vector3::new(point.x.floor(), point.y.floor(), point.z.floor());
+        let seed = hash(cell);
+        if seed > STAR_DENSITY {
+            return base;
+        }
+        let offset = Vector3::new(hash(cell + Vector3::one()), hash(cell * 2.0), hash(cell * 3.0 + Vector3::one()));
+        let star_direction = (cell + offset).normalized();
+        let distance = (1.0 - direction.normalized().dot(star_direction)).max(0.0).sqrt() * STAR_GRID;
+        let brightness = (1.0 - smoothstep(0.0, 0.2, distance)) * (seed / STAR_DENSITY) * self.stars;
+        // Cerca del horizonte la atmósfera las apaga
+        base + Vector3::new(0.9, 0.95, 1.0) * (brightness * smoothstep(0.0, 0.15, direction.y))
+    }
+
+    /// Color of sunlight after crossing the atmosphere: white at noon, orange and dim at sunset
+    pub fn sun_color(&self) -> Vector3 {
+        let elevation = self.sun_direction.y.clamp(0.0, 1.0).asin().to_degrees();
+        // Masa de aire de Kasten y Young, que sigue siendo finita en el horizonte
+        let air_mass = 1.0 / (elevation.to_radians().sin() + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+        let optical_depth = SUN_EXTINCTION * (self.turbidity * air_mass);
+        Vector3::new(
+            (-optical_depth.x).exp(),
+            (-optical_depth.y).exp(),
+            (-optical_depth.z).exp(),
+        ) * self.daylight()
+    }
+
+    pub fn sun_disk(&self, direction: Vector3) -> Vector3 {
+        let cos_angle = direction.normalized().dot(self.sun_direction);
+        let cos_edge = self.sun_size.cos();
+        if cos_angle < cos_edge || direction.y < 0.0 {
+            return Vector3::zero();
+        }
+        // Borde suavizado para que el disco no se vea dentado
+        let edge = smoothstep(cos_edge, cos_edge + (1.0 - cos_edge) * 0.3, cos_angle);
+        self.sun_color() * (self.sun_intensity * SUN_DISK_BRIGHTNESS * edge)
+    }
+
+    pub fn moon_disk(&self, direction: Vector3) -> Vector3 {
+        let cos_angle = direction.normalized().dot(self.moon_direction);
+        let cos_edge = (self.sun_size * 1.1).cos();
+        if cos_angle < cos_edge || direction.y < 0.0 {
+            return Vector3::zero();
+        }
+        let edge = smoothstep(cos_edge, cos_edge + (1.0 - cos_edge) * 0.3, cos_angle);
+        Vector3::new(0.85, 0.88, 0.95) * (MOON_DISK_BRIGHTNESS * edge)
+    }
+
+    pub fn sun_light(&self) -> Light {
+        let (color, intensity) = split_radiance(self.sun_color() * self.sun_intensity);
+        Light::directional(-self.sun_direction, color, intensity)
+    }
+
+    pub fn moon_light(&self) -> Light {
+        let moonrise = smoothstep(-0.05, 0.1, self.moon_direction.y);
+        let (color, intensity) = split_radiance(MOONLIGHT_COLOR * (self.moon_intensity * moonrise));
+        Light::directional(-self.moon_direction, color, intensity)
+    }
+}
+
+/// Luminance and chromaticity of the zenith for turbidity `t` and sun zenith angle `theta_sun`
+fn zenith(t: f32, theta_sun: f32) -> (f32, f32, f32) {
+    let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_sun);
+    let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+
+    let t2 = t * t;
+    let theta2 = theta_sun * theta_sun;
+    let theta3 = theta2 * theta_sun;
+    let x = (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta_sun) * t2
+        + (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta_sun + 0.00394) * t
+        + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta_sun + 0.25886);
+    let y = (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta_sun) * t2
+        + (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta_sun + 0.00516) * t
+        + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta_sun + 0.26688);
+    (luminance, x, y)
+}
+
+/// Pseudo-random value in [0, 1) that always comes out the same for the same cell
+fn hash(cell: Vector3) -> f32 {
+    let value = (cell.x * 127.1 + cell.y * 311.7 + cell.z * 74.7).sin() * 43_758.547;
+    value - value.floor()
+}
+
+fn xyy_to_rgb(x: f32, y: f32, big_y: f32) -> Vector3 {
+    if y <= 0.0 {
+        return Vector3::zero();
+    }
+    let big_x = x / y * big_y;
+    let big_z = (1.0 - x - y) / y * big_y;
+    Vector3::new(
+        (3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z).max(0.0),
+    )
+}
@@ -1,6 +1,7 @@
 use raylib::prelude::Vector3;
 use crate::ray_intersect::{Intersect, RayIntersect};
 use crate::material::Material;
+use crate::bvh::Aabb;
 use std::f32::consts::PI;
 
 pub struct Sphere {
@@ -29,7 +30,10 @@ impl RayIntersect for Sphere {
         let discriminant = b * b - 4.0 * a * c;
 
         if discriminant > 0.0 {
-            let t = (-b - discriminant.sqrt()) / (2.0 * a);
+            // Si el origen está dentro de la esfera (rayos refractados), usar la salida
+            let t_near = (-b - discriminant.sqrt()) / (2.0 * a);
+            let t_far = (-b + discriminant.sqrt()) / (2.0 * a);
+            let t = if t_near > 0.0 { t_near } else { t_far };
             if t > 0.0 {
                 let point = *ray_origin + *ray_direction * t;
                 let normal = (point - self.center).normalized();
@@ -42,4 +46,25 @@ impl RayIntersect for Sphere {
 
         Intersect::empty()
     }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let radius = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - radius, self.center + radius)
+    }
+
+    fn sample_surface(&self, from: Vector3) -> (Vector3, Vector3, f32) {
+        // Dirección uniforme sobre la esfera, volteada al hemisferio que mira hacia `from`
+        let z = 1.0 - 2.0 * rand::random::<f32>();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rand::random::<f32>();
+        let mut direction = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+        if direction.dot(from - self.center) < 0.0 {
+            direction = -direction;
+        }
+        (self.center + direction * self.radius, direction, 2.0 * PI * self.radius * self.radius)
+    }
 }
@@ -3,10 +3,12 @@
 use raylib::prelude::*;
 use std::collections::HashMap;
 
+use crate::tonemap::decode_srgb;
+
 struct CpuTexture {
     width: i32,
     height: i32,
-    pixels: Vec<Vector3>, // Normalized RGB values
+    pixels: Vec<Vector3>, // Normalized RGB values, still sRGB-encoded
 }
 
 impl CpuTexture {
@@ -32,9 +34,9 @@ impl CpuTexture {
     }
 }
 
+#[derive(Default)]
 pub struct TextureManager {
     cpu_textures: HashMap<String, CpuTexture>,
-    textures: HashMap<String, Texture2D>, // Store GPU textures for rendering
 }
 
 impl TextureManager {
@@ -42,29 +44,21 @@ impl TextureManager {
         Self::default()
     }
 
-    pub fn load_texture(
-        &mut self,
-        rl: &mut RaylibHandle,
-        thread: &RaylibThread,
-        path: &str,
-    ) {
-        if self.textures.contains_key(path) {
-            return;
+    pub fn load_texture(&mut self, path: &str) -> Result<(), String> {
+        if self.cpu_textures.contains_key(path) {
+            return Ok(());
         }
 
         let image = Image::load_image(path)
-            .unwrap_or_else(|_| panic!("Failed to load image {}", path));
-
-        let texture = rl
-            .load_texture_from_image(thread, &image)
-            .unwrap_or_else(|_| panic!("Failed to load texture {}", path));
+            .map_err(|err| format!("could not load texture {}: {}", path, err))?;
 
         let cpu_texture = CpuTexture::from_image(&image);
 
         self.cpu_textures.insert(path.to_string(), cpu_texture);
-        self.textures.insert(path.to_string(), texture);
+        Ok(())
     }
 
+    /// Linear RGB color of a texel, for textures that store colors
     pub fn get_pixel_color(
         &self,
         path: &str,
@@ -81,7 +75,7 @@ impl TextureManager {
 
             let index = (y * cpu_texture.width + x) as usize;
             if index < cpu_texture.pixels.len() {
-                cpu_texture.pixels[index]
+                decode_srgb(cpu_texture.pixels[index])
             } else {
                 Vector3::one()
             }
@@ -90,11 +84,13 @@ impl TextureManager {
         }
     }
 
-    pub fn get_texture(
+    pub fn get_size(
         &self,
         path: &str,
-    ) -> Option<&Texture2D> {
-        self.textures.get(path)
+    ) -> Option<(u32, u32)> {
+        self.cpu_textures
+            .get(path)
+            .map(|texture| (texture.width as u32, texture.height as u32))
     }
 
     pub fn get_normal_from_map(
@@ -128,12 +124,3 @@ impl TextureManager {
         }
     }
 }
-
-impl Default for TextureManager {
-    fn default() -> Self {
-        TextureManager {
-            cpu_textures: HashMap::new(),
-            textures: HashMap::new(),
-        }
-    }
-}
\ No newline at end of file
@@ -0,0 +1,74 @@
+// tonemap.rs
+
+use raylib::prelude::{Color, Vector3};
+
+use crate::material::vector3_to_color;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ToneMapping {
+    Clamp,
+    Reinhard,
+    /// Filmic curve fitted to the ACES reference transform (Narkowicz)
+    Aces,
+}
+
+impl ToneMapping {
+    pub fn next(self) -> ToneMapping {
+        match self {
+            ToneMapping::Clamp => ToneMapping::Reinhard,
+            ToneMapping::Reinhard => ToneMapping::Aces,
+            ToneMapping::Aces => ToneMapping::Clamp,
+        }
+    }
+
+    pub fn apply(self, color: Vector3) -> Vector3 {
+        let map = |c: f32| match self {
+            ToneMapping::Clamp => c.min(1.0),
+            ToneMapping::Reinhard => c / (1.0 + c),
+            ToneMapping::Aces => {
+                (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+            }
+        };
+        Vector3::new(map(color.x), map(color.y), map(color.z))
+    }
+}
+
+/// Mid-gray that auto exposure maps the average scene luminance to
+pub const MIDDLE_GRAY: f32 = 0.18;
+
+pub fn luminance(color: Vector3) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn decode_srgb(color: Vector3) -> Vector3 {
+    Vector3::new(srgb_to_linear(color.x), srgb_to_linear(color.y), srgb_to_linear(color.z))
+}
+
+pub fn encode(radiance: Vector3, tone_mapping: ToneMapping) -> Color {
+    // Los NaN y valores negativos de muestras degeneradas se tratan como negro
+    let sanitize = |c: f32| if c.is_finite() { c.max(0.0) } else { 0.0 };
+    let radiance = Vector3::new(sanitize(radiance.x), sanitize(radiance.y), sanitize(radiance.z));
+
+    let mapped = tone_mapping.apply(radiance);
+    vector3_to_color(Vector3::new(
+        linear_to_srgb(mapped.x.clamp(0.0, 1.0)),
+        linear_to_srgb(mapped.y.clamp(0.0, 1.0)),
+        linear_to_srgb(mapped.z.clamp(0.0, 1.0)),
+    ))
+}